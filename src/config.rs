@@ -0,0 +1,802 @@
+/*
+    Copyright (C) 2023 Aurora McGinnis
+
+    This Source Code Form is subject to the terms of the Mozilla Public
+    License, v. 2.0. If a copy of the MPL was not distributed with this
+    file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+    config.rs: Serializer output policies, Deserializer input policies and
+    their builders.
+*/
+
+use std::io::Write;
+
+use crate::decode::{Deserializer, Inference, Recovery};
+use crate::encode::Serializer;
+use crate::util::valid_in_ident;
+
+/// Output policies used by a [`Serializer`].
+///
+/// A `SerializerConfig` is built once with a [`SerializerBuilder`] and can then be
+/// handed to any number of serializers, including ones on other threads, via
+/// `Serializer::with_config`. The default configuration produces the same output
+/// as `Serializer::new`.
+#[derive(Clone, Debug)]
+pub struct SerializerConfig {
+    pub(crate) bools: BoolStyle,
+    pub(crate) none: NullStyle,
+    pub(crate) unit: NullStyle,
+    pub(crate) variants: VariantStyle,
+    pub(crate) tagging: TagStyle,
+    pub(crate) bytes: BytesStyle,
+    pub(crate) floats: FloatStyle,
+    pub(crate) non_finite: NonFiniteStyle,
+    pub(crate) escapes: EscapeStyle,
+    pub(crate) controls: ControlStyle,
+    pub(crate) ascii_only: bool,
+    pub(crate) hardened: bool,
+    pub(crate) keys: KeyStyle,
+    pub(crate) empty_keys: EmptyKeyStyle,
+    pub(crate) separator: char,
+    pub(crate) indices: IndexStyle,
+    pub(crate) seqs: SeqStyle,
+    pub(crate) quote_empty: bool,
+    pub(crate) empty_containers: EmptyContainerStyle,
+    #[cfg(feature = "json")]
+    pub(crate) json_depth: Option<usize>,
+    #[cfg(feature = "json")]
+    pub(crate) json_keys: Vec<String>,
+}
+
+impl Default for SerializerConfig {
+    fn default() -> Self {
+        SerializerConfig {
+            bools: BoolStyle::default(),
+            none: NullStyle::Null,
+            unit: NullStyle::Empty,
+            variants: VariantStyle::default(),
+            tagging: TagStyle::default(),
+            bytes: BytesStyle::default(),
+            floats: FloatStyle::default(),
+            non_finite: NonFiniteStyle::default(),
+            escapes: EscapeStyle::default(),
+            controls: ControlStyle::default(),
+            ascii_only: false,
+            hardened: false,
+            keys: KeyStyle::default(),
+            empty_keys: EmptyKeyStyle::default(),
+            separator: '.',
+            indices: IndexStyle::default(),
+            seqs: SeqStyle::default(),
+            quote_empty: false,
+            empty_containers: EmptyContainerStyle::default(),
+            #[cfg(feature = "json")]
+            json_depth: None,
+            #[cfg(feature = "json")]
+            json_keys: Vec::new(),
+        }
+    }
+}
+
+/// Controls how booleans are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BoolStyle {
+    /// `true` is written as a bare key and `false` is not written at all.
+    ///
+    /// Reading this back needs `#[serde(default)]` on `bool` fields, or
+    /// [`DeserializerBuilder::with_missing_bools_false`].
+    #[default]
+    Bare,
+    /// `true` is written as a bare key and `false` as `key=false`.
+    BareTrue,
+    /// Booleans are always written as `key=true` or `key=false`.
+    Explicit,
+}
+
+/// Controls how a value with nothing in it, i.e. `None` or `()`, is written.
+///
+/// The style applies wherever the value appears, be it a struct field, a map value
+/// or a sequence element.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NullStyle {
+    /// The pair is not written at all. Omitted sequence elements leave a gap
+    /// in the indices of the elements after them, which the deserializer fills
+    /// with `None`. Omitted elements at the end of a sequence are lost.
+    Omit,
+    /// The key is written with an empty value, e.g. `key=`.
+    Empty,
+    /// The key is written with the value `null`, e.g. `key=null`.
+    Null,
+    /// The key is written with the given value, e.g. `key=-`.
+    Token(String),
+}
+
+/// Controls how unit enum variants are written.
+///
+/// Names are taken from serde, so `#[serde(rename)]` and `#[serde(rename_all)]`
+/// are respected. The [`Deserializer`](crate::Deserializer) reads variants
+/// written in any of these styles.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VariantStyle {
+    /// The enum and variant name, e.g. `level=Level::Info`.
+    #[default]
+    Qualified,
+    /// The variant name, e.g. `level=Info`.
+    Name,
+    /// The variant name in lower case, e.g. `level=info`.
+    Lowercase,
+    /// The variant name in snake case, e.g. `status=not_found` for `NotFound`.
+    SnakeCase,
+    /// The index of the variant, e.g. `level=1`.
+    Index,
+}
+
+/// Controls how the variant of a newtype, tuple or struct enum variant is written.
+/// Unit variants are always written as a value in the configured [`VariantStyle`].
+///
+/// A newtype variant holding a value that would otherwise write nothing, such as
+/// `false` with [`BoolStyle::Bare`] or `None` with [`NullStyle::Omit`], is
+/// written explicitly, e.g. `enum_val.Variant3=false` or `enum_val.Variant3=null`.
+///
+/// Use the same style with [`DeserializerBuilder::with_tagging`] to read
+/// adjacently tagged variants back.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TagStyle {
+    /// Only the content of the variant is written, e.g. `enum_val=389384893`.
+    /// Different variants holding the same types can't be told apart.
+    #[default]
+    Untagged,
+    /// The content is written under the name of the variant, e.g.
+    /// `enum_val.Variant3=389384893`.
+    External,
+    /// The name of the variant and its content are written under separate keys,
+    /// e.g. `enum_val.type=Variant3 enum_val.value=389384893` with `tag` set to
+    /// `type` and `content` set to `value`.
+    Adjacent { tag: String, content: String },
+}
+
+/// Controls how byte arrays, e.g. fields using `serde_bytes`, are written.
+///
+/// Bytes are encoded as they are written, without building an intermediate
+/// string, so large payloads don't need extra memory. Use the same style with
+/// [`DeserializerBuilder::with_bytes`] to read them back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BytesStyle {
+    /// Upper case hex, e.g. `b=FF0143`.
+    #[default]
+    UpperHex,
+    /// Lower case hex, e.g. `b=ff0143`.
+    LowerHex,
+    /// Padded base64 using the standard alphabet, e.g. `b="/wFD"`. The value
+    /// is quoted if it ends in padding.
+    Base64,
+    /// Unpadded base64 using the URL and filename safe alphabet, e.g. `b=_wFD`.
+    Base64Url,
+    /// Text if the bytes are valid UTF-8, e.g. `b="GET /"`, and upper case hex
+    /// otherwise.
+    Utf8,
+}
+
+/// Controls how finite floating point numbers are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FloatStyle {
+    /// The shortest representation that reads back as the same number, which
+    /// may use an exponent, e.g. `1.5`, `1e-7`.
+    #[default]
+    Shortest,
+    /// Exactly the given number of decimal places, e.g. `12.50` for 2.
+    Fixed(usize),
+    /// Rounded to the given number of significant digits, clamped to 1 to 17, and
+    /// written without an exponent, e.g. `12300.0` for 3. Values that would round
+    /// past the largest float are written unrounded.
+    Significant(usize),
+    /// The shortest representation that reads back as the same number, written
+    /// without an exponent, e.g. `0.0000001`.
+    Plain,
+}
+
+/// Controls how `NaN` and infinite floating point numbers are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NonFiniteStyle {
+    /// The value is written as `NaN`, `inf` or `-inf`.
+    #[default]
+    Token,
+    /// The value is written as `null`.
+    Null,
+    /// The pair is not written at all, unless it is the payload of a newtype
+    /// variant, which is written as `null` so the variant isn't lost.
+    Omit,
+    /// Serialization fails.
+    Error,
+}
+
+/// Controls how backslashes and control characters in quoted values are escaped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EscapeStyle {
+    /// Escape sequences already in the value, such as a literal `\n`, are written
+    /// as they are, and control characters other than `\0`, `\t`, `\n` and `\r`
+    /// are replaced with their Unicode control pictures. Readable, but a value
+    /// may not decode to exactly what was written.
+    #[default]
+    PassThrough,
+    /// Every backslash is escaped, and control characters other than `\0`, `\t`,
+    /// `\n` and `\r` are written as `\xNN` unless another [`ControlStyle`] is
+    /// chosen, so every value decodes to exactly what was written.
+    Lossless,
+}
+
+/// Controls how control characters in quoted values are escaped. `\t`, `\n`
+/// and `\r` are always written as such.
+///
+/// Every style but [`ControlStyle::Pictures`] escapes the C1 control characters
+/// (U+0080 to U+009F) too, and the escapes they write are understood by the
+/// [`Deserializer`](crate::Deserializer).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ControlStyle {
+    /// NUL is written as `\0` and the other ASCII control characters are replaced
+    /// with their Unicode control pictures, e.g. `␛` for ESC. This is lossy, as a
+    /// real `␛` looks the same.
+    #[default]
+    Pictures,
+    /// NUL is written as `\0` and the others as `\xNN`, e.g. `\x1b`.
+    Hex,
+    /// JSON style `\u00NN` escapes, e.g. `\u001b`.
+    Unicode,
+    /// Escapes compatible with Go's `strconv.Quote`: `\a`, `\b`, `\f`, `\v`,
+    /// `\xNN` for other ASCII control characters, and `\uNNNN` or `\UNNNNNNNN`
+    /// for C1 control characters, Unicode whitespace other than space and
+    /// format characters such as `\u200b` and `\u202e`.
+    Go,
+}
+
+/// Controls what is done with characters that cannot appear in a key as they
+/// are, such as spaces, `=` and `"`. Characters excluded by
+/// [`SerializerBuilder::with_ascii_only`] or
+/// [`SerializerBuilder::with_hardened`] count as invalid too.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyStyle {
+    /// Invalid characters are percent-encoded as UTF-8, e.g. `has%20spaces`, and
+    /// so is `%` itself. The [`Deserializer`](crate::Deserializer) decodes them
+    /// again.
+    #[default]
+    Percent,
+    /// Invalid characters are replaced with the given character, e.g.
+    /// `has_spaces`. A replacement that is itself invalid is percent-encoded.
+    Replace(char),
+    /// Invalid characters are left out, e.g. `hasspaces`.
+    Strip,
+    /// Keys with invalid characters are rejected with
+    /// [`Error::InvalidIdentifier`](crate::Error::InvalidIdentifier).
+    Error,
+}
+
+/// Controls what is done with struct fields and map keys that would be written
+/// as an empty key, either because they are empty or because
+/// [`KeyStyle::Strip`] left nothing of them. The dotted keys of a
+/// [`Record`](crate::Record) are checked segment by segment, e.g. `a..b`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum EmptyKeyStyle {
+    /// The whole document is rejected with
+    /// [`Error::EmptyIdentifier`](crate::Error::EmptyIdentifier).
+    #[default]
+    Error,
+    /// The key is replaced with the given placeholder, e.g. `_empty`.
+    Placeholder(String),
+    /// The pair is not written at all.
+    Skip,
+}
+
+/// Controls how sequence and tuple indices are written in keys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IndexStyle {
+    /// Indices are segments of their own, e.g. `nums.0`.
+    #[default]
+    Separated,
+    /// Indices are written in brackets after their sequence, e.g. `nums[0]`.
+    /// Brackets in other segments are escaped.
+    Brackets,
+}
+
+/// Controls how sequences, tuples and arrays are written.
+///
+/// Elements are written in the compact styles as they would be on their own,
+/// but unescaped, with booleans always written as `true` or `false`, and
+/// elements that write nothing (such as an omitted `None`) are left out. The
+/// joined value is then quoted and escaped as a whole. As soon as an element
+/// turns out to be a struct, map or sequence, even an empty one, the whole
+/// sequence is written as in [`SeqStyle::Exploded`] instead.
+///
+/// Elements that couldn't be told apart once joined fail serialization:
+/// strings that contain the delimiter, and strings that are always quoted,
+/// such as [`Value::String`](crate::Value::String).
+///
+/// The compact styles are write-only: the [`Deserializer`] doesn't split them
+/// back into sequences, but they can be read as a single string, e.g.
+/// `nums=1,2` as `"1,2"`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SeqStyle {
+    /// Each element is a pair of its own, e.g. `nums.0=1 nums.1=2`.
+    #[default]
+    Exploded,
+    /// The elements are joined with the given delimiter into a single value,
+    /// e.g. `nums=1,2`. Empty sequences are not written at all.
+    Joined(char),
+    /// The elements are separated by commas in brackets, e.g. `nums=[1,2]`.
+    Bracketed,
+}
+
+/// Controls how structs, maps and sequences that have nothing to write are
+/// written, such as empty `Vec`s or structs whose fields are all omitted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyContainerStyle {
+    /// Nothing is written, so an empty container looks like a missing one.
+    #[default]
+    Omit,
+    /// `key=[]` for sequences and `key={}` for structs and maps.
+    Brackets,
+    /// `key=` for any container.
+    Empty,
+}
+
+/// Builds a [`SerializerConfig`] or a [`Serializer`] using it.
+///
+/// ```rust
+/// use alogfmt::Serializer;
+/// use anyhow::Result;
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct MyStruct {
+///    pub ts: u64,
+///    pub message: String,
+/// }
+///
+/// fn main() -> Result<()> {
+///    let s = MyStruct{
+///        ts: 1690232215,
+///        message: String::from("Hello World!"),
+///    };
+///
+///    let mut serializer = Serializer::builder().build(Vec::new());
+///    s.serialize(&mut serializer)?;
+///
+///    let result = unsafe {
+///        String::from_utf8_unchecked(serializer.writer())
+///    };
+///
+///    assert_eq!(result, "ts=1690232215 message=\"Hello World!\"");
+///
+///    Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SerializerBuilder {
+    config: SerializerConfig,
+}
+
+impl SerializerBuilder {
+    /// Construct a new `SerializerBuilder` starting from the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how booleans are written.
+    #[inline]
+    pub fn with_bools(mut self, bools: BoolStyle) -> Self {
+        self.config.bools = bools;
+        self
+    }
+
+    /// Sets how `None` is written. Defaults to [`NullStyle::Null`].
+    #[inline]
+    pub fn with_none(mut self, none: NullStyle) -> Self {
+        self.config.none = none;
+        self
+    }
+
+    /// Sets how `()` and unit structs are written. Defaults to [`NullStyle::Empty`].
+    #[inline]
+    pub fn with_unit(mut self, unit: NullStyle) -> Self {
+        self.config.unit = unit;
+        self
+    }
+
+    /// Sets how unit enum variants are written.
+    #[inline]
+    pub fn with_variants(mut self, variants: VariantStyle) -> Self {
+        self.config.variants = variants;
+        self
+    }
+
+    /// Sets how the variants of enums holding data are written.
+    #[inline]
+    pub fn with_tagging(mut self, tagging: TagStyle) -> Self {
+        self.config.tagging = tagging;
+        self
+    }
+
+    /// Sets how byte arrays are written.
+    #[inline]
+    pub fn with_bytes(mut self, bytes: BytesStyle) -> Self {
+        self.config.bytes = bytes;
+        self
+    }
+
+    /// Sets how finite floats are written. [`FloatStyle::Significant`] digits
+    /// are clamped to 1 to 17.
+    #[inline]
+    pub fn with_floats(mut self, mut floats: FloatStyle) -> Self {
+        if let FloatStyle::Significant(digits) = &mut floats {
+            *digits = (*digits).clamp(1, 17);
+        }
+
+        self.config.floats = floats;
+        self
+    }
+
+    /// Sets how `NaN` and infinite floats are written.
+    #[inline]
+    pub fn with_non_finite(mut self, non_finite: NonFiniteStyle) -> Self {
+        self.config.non_finite = non_finite;
+        self
+    }
+
+    /// Sets how backslashes and control characters in values are escaped.
+    #[inline]
+    pub fn with_escapes(mut self, escapes: EscapeStyle) -> Self {
+        self.config.escapes = escapes;
+        self
+    }
+
+    /// Sets how control characters in values are escaped.
+    #[inline]
+    pub fn with_controls(mut self, controls: ControlStyle) -> Self {
+        self.config.controls = controls;
+        self
+    }
+
+    /// If true, only ASCII is written. Other characters are percent-encoded in
+    /// keys and escaped as `\uNNNN` in values, or `\u{NNNNN}` outside the Basic
+    /// Multilingual Plane. [`ControlStyle::Go`] uses `\UNNNNNNNN` for the latter,
+    /// and [`ControlStyle::Pictures`] is replaced by [`ControlStyle::Hex`]. Keys
+    /// follow the [`KeyStyle`] instead if it is not [`KeyStyle::Percent`].
+    #[inline]
+    pub fn with_ascii_only(mut self, ascii_only: bool) -> Self {
+        self.config.ascii_only = ascii_only;
+        self
+    }
+
+    /// If true, characters that can make a line look different from what it
+    /// contains are escaped in both keys and values: whitespace other than space,
+    /// line and paragraph separators, invisible format characters, bidi controls
+    /// and C1 control characters such as CSI. This stops values from visually adding fake pairs to a line or
+    /// reordering it, as in Trojan Source attacks. Values are escaped as
+    /// `\uNNNN` and keys follow the [`KeyStyle`], percent-encoding by default.
+    #[inline]
+    pub fn with_hardened(mut self, hardened: bool) -> Self {
+        self.config.hardened = hardened;
+        self
+    }
+
+    /// Sets what is done with characters that are not valid in keys.
+    #[inline]
+    pub fn with_keys(mut self, keys: KeyStyle) -> Self {
+        self.config.keys = keys;
+        self
+    }
+
+    /// Sets what is done with keys that would be written empty.
+    #[inline]
+    pub fn with_empty_keys(mut self, empty_keys: EmptyKeyStyle) -> Self {
+        self.config.empty_keys = empty_keys;
+        self
+    }
+
+    /// Sets the character nested keys are joined with, e.g. `_` for `user_name`.
+    /// Defaults to `.`. The separator is escaped inside segments according to the
+    /// [`KeyStyle`], so map keys containing it don't split into several segments.
+    ///
+    /// # Panics
+    /// This function panics if the separator is not valid in a key, or is `%`.
+    #[inline]
+    pub fn with_separator(mut self, separator: char) -> Self {
+        assert!(
+            valid_in_ident(separator) && separator != '%',
+            "{separator:?} cannot be used as a key separator"
+        );
+
+        self.config.separator = separator;
+        self
+    }
+
+    /// Sets how sequence and tuple indices are written in keys.
+    #[inline]
+    pub fn with_indices(mut self, indices: IndexStyle) -> Self {
+        self.config.indices = indices;
+        self
+    }
+
+    /// Sets how sequences, tuples and arrays are written.
+    #[inline]
+    pub fn with_seqs(mut self, seqs: SeqStyle) -> Self {
+        self.config.seqs = seqs;
+        self
+    }
+
+    /// Writes structs, maps and sequences whose key has at least `depth`
+    /// segments as a single JSON value instead of flattening them, e.g.
+    /// `ctx="{\"user\":{\"id\":3}}"` with a depth of 1. Indices count as
+    /// segments.
+    ///
+    /// The JSON is written by `serde_json` as it would be on its own, so the rest
+    /// of this configuration doesn't apply inside it: non-finite floats become
+    /// `null` whatever the [`NonFiniteStyle`], bytes are arrays of numbers rather
+    /// than [`BytesStyle`], and enums are externally tagged whatever the
+    /// [`TagStyle`]. The quoted value holding it is escaped as in
+    /// [`EscapeStyle::Lossless`] whatever the configured style, so that it reads
+    /// back as the same JSON.
+    #[cfg(feature = "json")]
+    #[inline]
+    pub fn with_json_depth(mut self, depth: usize) -> Self {
+        self.config.json_depth = Some(depth);
+        self
+    }
+
+    /// Writes structs, maps and sequences under the given keys as a single JSON
+    /// value instead of flattening them. Keys are given as they are written
+    /// but unescaped, e.g. `req.headers`. As with
+    /// [`with_json_depth`](Self::with_json_depth), the configuration doesn't
+    /// apply inside the JSON.
+    #[cfg(feature = "json")]
+    pub fn with_json_keys<I>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.config.json_keys = keys.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// If true, empty strings are written as `key=""` rather than `key=`, so
+    /// they can be told apart from empty containers and [`NullStyle::Empty`].
+    #[inline]
+    pub fn with_quoted_empty(mut self, quote_empty: bool) -> Self {
+        self.config.quote_empty = quote_empty;
+        self
+    }
+
+    /// Sets how structs, maps and sequences with nothing to write are written.
+    #[inline]
+    pub fn with_empty_containers(mut self, empty_containers: EmptyContainerStyle) -> Self {
+        self.config.empty_containers = empty_containers;
+        self
+    }
+
+    /// Returns the configuration built so far.
+    #[inline]
+    pub fn config(self) -> SerializerConfig {
+        self.config
+    }
+
+    /// Construct a new `Serializer` that writes to the supplied object
+    /// implementing `Write` using the configuration built so far.
+    #[inline]
+    pub fn build<B: Write>(self, writer: B) -> Serializer<B> {
+        Serializer::with_config(writer, self.config)
+    }
+}
+
+impl From<SerializerConfig> for SerializerBuilder {
+    fn from(config: SerializerConfig) -> Self {
+        SerializerBuilder { config }
+    }
+}
+
+/// Input policies used by a [`Deserializer`] or a [`Reader`](crate::Reader).
+///
+/// A `DeserializerConfig` is built once with a [`DeserializerBuilder`] and can
+/// then be handed to any number of deserializers and readers via
+/// `Deserializer::with_config` and `Reader::with_config`. The default
+/// configuration reads what `Serializer::new` writes.
+#[derive(Clone, Debug)]
+pub struct DeserializerConfig {
+    pub(crate) inference: Inference,
+    pub(crate) recovery: Recovery,
+    pub(crate) missing_bools_false: bool,
+    pub(crate) none: NullStyle,
+    pub(crate) unit: NullStyle,
+    pub(crate) bytes: BytesStyle,
+    pub(crate) tagging: TagStyle,
+    pub(crate) separator: char,
+    pub(crate) indices: IndexStyle,
+}
+
+impl Default for DeserializerConfig {
+    fn default() -> Self {
+        DeserializerConfig {
+            inference: Inference::default(),
+            recovery: Recovery::default(),
+            missing_bools_false: false,
+            none: NullStyle::Null,
+            unit: NullStyle::Empty,
+            bytes: BytesStyle::default(),
+            tagging: TagStyle::default(),
+            separator: '.',
+            indices: IndexStyle::default(),
+        }
+    }
+}
+
+/// Builds a [`DeserializerConfig`] or a [`Deserializer`] using it.
+///
+/// ```rust
+/// use alogfmt::{Deserializer, Recovery};
+/// use anyhow::Result;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct MyStruct {
+///    pub ts: u64,
+///    pub _unparsed: Option<String>,
+/// }
+///
+/// fn main() -> Result<()> {
+///    let mut deserializer = Deserializer::builder()
+///        .with_recovery(Recovery::Capture(String::from("_unparsed")))
+///        .build("ts=1690232215 message=\"truncat");
+///    let s = MyStruct::deserialize(&mut deserializer)?;
+///
+///    assert_eq!(s.ts, 1690232215);
+///    assert_eq!(s._unparsed.as_deref(), Some("message=\"truncat"));
+///
+///    Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DeserializerBuilder {
+    config: DeserializerConfig,
+}
+
+impl DeserializerBuilder {
+    /// Construct a new `DeserializerBuilder` starting from the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the policy used to infer the types of values.
+    #[inline]
+    pub fn with_inference(mut self, inference: Inference) -> Self {
+        self.config.inference = inference;
+        self
+    }
+
+    /// Sets the policy used to handle malformed pairs.
+    #[inline]
+    pub fn with_recovery(mut self, recovery: Recovery) -> Self {
+        self.config.recovery = recovery;
+        self
+    }
+
+    /// Sets whether struct fields missing from the input are read as `false` if
+    /// they are `bool`s, for reading documents written with [`BoolStyle::Bare`].
+    /// Defaults to `false`.
+    ///
+    /// Whether a field is a `bool` is only known once it is read, so with this
+    /// enabled every field missing from the input is read: `bool`s as `false`,
+    /// `Option`s as `None`, and anything else as its empty value, such as `0`,
+    /// `""` or an empty collection. This is also what `#[serde(default)]` gives
+    /// those types, but a `#[serde(default = "...")]` function isn't called.
+    #[inline]
+    pub fn with_missing_bools_false(mut self, missing_bools_false: bool) -> Self {
+        self.config.missing_bools_false = missing_bools_false;
+        self
+    }
+
+    /// Sets the style `None` was written in. Defaults to [`NullStyle::Null`].
+    /// An unquoted `null` is read as `None` in any style, and so is an empty
+    /// document, which is what [`NullStyle::Omit`] writes for a `None` on its own.
+    #[inline]
+    pub fn with_none(mut self, none: NullStyle) -> Self {
+        self.config.none = none;
+        self
+    }
+
+    /// Sets the style `()` and unit structs were written in. Defaults to
+    /// [`NullStyle::Empty`]. An empty value is read as `()` in any style.
+    #[inline]
+    pub fn with_unit(mut self, unit: NullStyle) -> Self {
+        self.config.unit = unit;
+        self
+    }
+
+    /// Sets the style byte arrays were written in. Defaults to
+    /// [`BytesStyle::UpperHex`], and either case of hex is read in the hex styles.
+    ///
+    /// With [`BytesStyle::Utf8`], values are read as the bytes of their text, so
+    /// bytes that were written as hex because they weren't UTF-8 come back as the
+    /// hex digits.
+    #[inline]
+    pub fn with_bytes(mut self, bytes: BytesStyle) -> Self {
+        self.config.bytes = bytes;
+        self
+    }
+
+    /// Sets the style enum variants holding data were written in.
+    ///
+    /// Externally tagged variants are read in any style, so this is only needed
+    /// for [`TagStyle::Adjacent`], to know which keys hold the tag and content.
+    #[inline]
+    pub fn with_tagging(mut self, tagging: TagStyle) -> Self {
+        self.config.tagging = tagging;
+        self
+    }
+
+    /// Sets the character nested keys were joined with. Defaults to `.`.
+    #[inline]
+    pub fn with_separator(mut self, separator: char) -> Self {
+        self.config.separator = separator;
+        self
+    }
+
+    /// Sets the style sequence indices were written in.
+    #[inline]
+    pub fn with_indices(mut self, indices: IndexStyle) -> Self {
+        self.config.indices = indices;
+        self
+    }
+
+    /// Returns the configuration built so far.
+    #[inline]
+    pub fn config(self) -> DeserializerConfig {
+        self.config
+    }
+
+    /// Construct a new `Deserializer` that reads from the supplied string using
+    /// the configuration built so far.
+    #[inline]
+    pub fn build(self, input: &str) -> Deserializer<'_> {
+        Deserializer::from_str(input).with_config(self.config)
+    }
+}
+
+impl From<DeserializerConfig> for DeserializerBuilder {
+    fn from(config: DeserializerConfig) -> Self {
+        DeserializerBuilder { config }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SerializerBuilder, SerializerConfig};
+    use crate::Serializer;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_shared_config() {
+        let config = Arc::new(SerializerBuilder::new().config());
+
+        let handles: Vec<_> = (0..2)
+            .map(|i| {
+                let config = Arc::clone(&config);
+                thread::spawn(move || {
+                    let m: BTreeMap<_, _> = [("a", i), ("b", i + 1)].into_iter().collect();
+
+                    let mut serializer = Serializer::with_config(Vec::new(), config);
+                    m.serialize(&mut serializer).unwrap();
+                    String::from_utf8(serializer.writer()).unwrap()
+                })
+            })
+            .collect();
+
+        let out: Vec<String> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(out, ["a=0 b=1", "a=1 b=2"]);
+
+        let builder = SerializerBuilder::from(SerializerConfig::default());
+        let mut serializer = builder.build(Vec::new());
+        "value".serialize(&mut serializer).unwrap();
+        assert_eq!(serializer.writer(), b"value");
+    }
+}
@@ -0,0 +1,2060 @@
+/*
+    Copyright (C) 2023 Aurora McGinnis
+
+    This Source Code Form is subject to the terms of the Mozilla Public
+    License, v. 2.0. If a copy of the MPL was not distributed with this
+    file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+    decode.rs: Logfmt deserializer implementation.
+*/
+
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{
+    self, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, Unexpected,
+    VariantAccess, Visitor,
+};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::{
+    BytesStyle, DeserializerBuilder, DeserializerConfig, IndexStyle, NullStyle, TagStyle,
+};
+use crate::error::{Error, Result};
+use crate::lex::{percent_decode, scan_quoted, unescape, Token, TokenKind, Tokenizer};
+use crate::util::{decode_base64, to_snake_case, BASE64, BASE64_URL};
+use crate::value::{BARE_TOKEN, RECORD_TOKEN, VALUE_TOKEN};
+
+/// Provides a serde Deserializer implementation that understands the output
+/// of [`Serializer`](crate::Serializer).
+///
+/// A `Deserializer` reads exactly one logfmt document (i.e. one line). A single
+/// trailing line ending is permitted, so lines produced by `serializer.next()`
+/// can be fed back in as-is.
+///
+/// Keys are split on `.` to reverse the flattening done by the serializer, or on
+/// the separator given to [`DeserializerBuilder::with_separator`]. Pairs sharing a
+/// prefix are grouped into nested structs and maps wherever they appear in the
+/// line, and groups whose segments count up from zero (`nums.0=1 nums.1=2`) can
+/// be read as sequences.
+///
+/// Strings and keys are borrowed from the input whenever they contain no escape
+/// sequences, so types holding `&str` or `Cow<str>` can be deserialized without
+/// copying. Values that needed unescaping are only available as owned strings,
+/// so prefer `#[serde(borrow)] Cow<'a, str>` over `&'a str` for fields that may
+/// contain them.
+///
+/// By default, a malformed pair fails the whole document with an error reporting
+/// where and why parsing failed. Use [`DeserializerBuilder::with_recovery`] to skip
+/// malformed pairs or capture them under a key of their own instead.
+///
+/// Booleans are read in any [`BoolStyle`](crate::BoolStyle). Since the default
+/// style doesn't write `false` at all, `bool` fields need `#[serde(default)]` to
+/// be read back, unless [`DeserializerBuilder::with_missing_bools_false`] is
+/// enabled.
+///
+/// ```rust
+/// use alogfmt::Deserializer;
+/// use anyhow::Result;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize, Debug, PartialEq)]
+/// struct MyStruct {
+///    pub ts: u64,
+///    pub message: String,
+/// }
+///
+/// fn main() -> Result<()> {
+///    let mut deserializer = Deserializer::from_str("ts=1690232215 message=\"Hello World!\"\n");
+///    let s = MyStruct::deserialize(&mut deserializer)?;
+///
+///    assert_eq!(
+///        s,
+///        MyStruct {
+///            ts: 1690232215,
+///            message: String::from("Hello World!"),
+///        }
+///    );
+///
+///    Ok(())
+/// }
+/// ```
+pub struct Deserializer<'de> {
+    input: &'de str,
+    config: Arc<DeserializerConfig>,
+}
+
+/// Controls how values are interpreted when the type being deserialized does not
+/// say what it expects, e.g. when deserializing a [`Value`](crate::Value).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Inference {
+    /// Infer the type of unquoted values from their text: `null`, `true`, `false`,
+    /// integers as written by `itoa` and floats as written by `dtoa` (including
+    /// `NaN` and `inf`). Other values and all quoted values are strings.
+    ///
+    /// Only canonically formatted numbers are inferred, so a value with leading
+    /// zeros such as `02134` stays a string.
+    #[default]
+    Inferred,
+    /// Every value is a string. Bare keys are still `true`.
+    Strings,
+}
+
+/// Controls what happens when a pair in the input is malformed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Recovery {
+    /// Fail with an [`Error::SyntaxError`] that reports the byte offset and
+    /// reason of the first malformed pair.
+    #[default]
+    Strict,
+    /// Ignore malformed pairs and continue with the next one.
+    Skip,
+    /// Continue with the next pair, collecting the text of every malformed pair
+    /// into a single space-separated value under the given key. The value is
+    /// added after all other pairs, e.g. `_unparsed="a=\"b\"c =d"`.
+    Capture(String),
+}
+
+impl Deserializer<'static> {
+    /// Returns a [`DeserializerBuilder`] for configuring a new `Deserializer`.
+    #[inline]
+    pub fn builder() -> DeserializerBuilder {
+        DeserializerBuilder::new()
+    }
+}
+
+impl<'de> Deserializer<'de> {
+    /// Construct a new `Deserializer` that reads from the supplied string.
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &'de str) -> Self {
+        let input = input
+            .strip_suffix('\n')
+            .map(|s| s.strip_suffix('\r').unwrap_or(s))
+            .unwrap_or(input);
+
+        Deserializer {
+            input,
+            config: Arc::new(DeserializerConfig::default()),
+        }
+    }
+
+    /// Use the given configuration from now on. Pass an
+    /// `Arc<DeserializerConfig>` to share one configuration between
+    /// deserializers.
+    #[inline]
+    pub fn with_config<C>(mut self, config: C) -> Self
+    where
+        C: Into<Arc<DeserializerConfig>>,
+    {
+        self.config = config.into();
+        self
+    }
+
+    /// Construct a new `Deserializer` that reads from the supplied bytes.
+    ///
+    /// # Errors
+    /// This function will return an error if the input is not valid UTF-8.
+    pub fn from_slice(input: &'de [u8]) -> Result<Self> {
+        match std::str::from_utf8(input) {
+            Ok(s) => Ok(Self::from_str(s)),
+            Err(e) => Err(Error::SyntaxError {
+                offset: e.valid_up_to(),
+                reason: "invalid UTF-8",
+            }),
+        }
+    }
+
+    // Parses every pair in the input.
+    fn entries(&self) -> Result<Vec<Entry<'de>>> {
+        let mut tokens = Tokenizer::new(self.input);
+        let mut entries = Vec::with_capacity(16);
+        let mut unparsed = Vec::new();
+
+        while let Some(token) = tokens.next() {
+            let (val, end) = match token.kind {
+                TokenKind::Key => {
+                    let val = tokens
+                        .next()
+                        .expect("the tokenizer always yields a value after a key");
+                    (Some(Val::from_token(&val)), val.span.end)
+                }
+                TokenKind::BareKey => (None, token.span.end),
+                TokenKind::Error { offset, reason } => {
+                    self.recover(
+                        Error::SyntaxError { offset, reason },
+                        token.text,
+                        &mut unparsed,
+                    )?;
+                    continue;
+                }
+                TokenKind::Value => unreachable!("the tokenizer only yields values after keys"),
+            };
+
+            match split_key(&token, self.config.separator, self.config.indices) {
+                Ok(path) => entries.push(Entry { path, val }),
+                Err(e) => {
+                    let text = &self.input[token.span.start..end];
+                    self.recover(e, text, &mut unparsed)?;
+                }
+            }
+        }
+
+        if let (Recovery::Capture(key), false) = (&self.config.recovery, unparsed.is_empty()) {
+            let raw = match unparsed[..] {
+                [text] => Cow::Borrowed(text),
+                _ => Cow::Owned(unparsed.join(" ")),
+            };
+
+            entries.push(Entry {
+                path: vec![Cow::Owned(key.clone())],
+                val: Some(Val { raw, quoted: false }),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    // Parses every pair in the input, moving pairs that share a path prefix next
+    // to each other. Prefixes stay in the order they first appear in.
+    fn grouped_entries(&self) -> Result<Vec<Entry<'de>>> {
+        let entries = self.entries()?;
+
+        let mut first = HashMap::new();
+        let ranks: Vec<Vec<usize>> = entries
+            .iter()
+            .map(|entry| {
+                (1..=entry.path.len())
+                    .map(|len| {
+                        let next = first.len();
+                        *first.entry(&entry.path[..len]).or_insert(next)
+                    })
+                    .collect()
+            })
+            .collect();
+        drop(first);
+
+        let mut ranked: Vec<_> = ranks.into_iter().zip(entries).collect();
+        ranked.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(ranked.into_iter().map(|(_, entry)| entry).collect())
+    }
+
+    // Applies the recovery policy to a malformed pair.
+    fn recover(&self, err: Error, text: &'de str, unparsed: &mut Vec<&'de str>) -> Result<()> {
+        match self.config.recovery {
+            Recovery::Strict => Err(err),
+            Recovery::Skip => Ok(()),
+            Recovery::Capture(_) => {
+                unparsed.push(text);
+                Ok(())
+            }
+        }
+    }
+
+    // Interprets the entire input as a single value, which is what the
+    // serializer produces for anything that isn't a struct or a map.
+    fn value(&self) -> Result<ValueDeserializer<'_, 'de>> {
+        let input = self.input;
+
+        if !input.starts_with('"') {
+            return Ok(ValueDeserializer::new(
+                Val {
+                    raw: Cow::Borrowed(input),
+                    quoted: false,
+                },
+                &self.config,
+            ));
+        }
+
+        match scan_quoted(input, 0) {
+            Some(end) if end == input.len() => Ok(ValueDeserializer::new(
+                Val {
+                    raw: Cow::Borrowed(&input[1..end - 1]),
+                    quoted: true,
+                },
+                &self.config,
+            )),
+            Some(end) => Err(Error::SyntaxError {
+                offset: end,
+                reason: "trailing characters after value",
+            }),
+            None => Err(Error::SyntaxError {
+                offset: 0,
+                reason: "unterminated quoted value",
+            }),
+        }
+    }
+}
+
+// Splits a key token into its segments and decodes each of them.
+fn split_key<'de>(
+    token: &Token<'de>,
+    separator: char,
+    indices: IndexStyle,
+) -> Result<Vec<Cow<'de, str>>> {
+    let mut path = Vec::with_capacity(4);
+    let mut offset = token.span.start;
+
+    for segment in token.text.split(separator) {
+        // with brackets, indices follow their sequence in the same segment
+        let (name, mut rest) = match indices {
+            IndexStyle::Brackets => segment.split_at(segment.find('[').unwrap_or(segment.len())),
+            IndexStyle::Separated => (segment, ""),
+        };
+
+        if name.is_empty() && rest.is_empty() {
+            return Err(Error::SyntaxError {
+                offset,
+                reason: "empty key segment",
+            });
+        }
+
+        if !name.is_empty() {
+            path.push(percent_decode(name).ok_or(Error::SyntaxError {
+                offset,
+                reason: "percent-encoded key is not valid UTF-8",
+            })?);
+        }
+
+        let mut idx_offset = offset + name.len();
+        while !rest.is_empty() {
+            let index = rest
+                .strip_prefix('[')
+                .and_then(|r| r.split_once(']'))
+                .filter(|(idx, _)| !idx.is_empty() && idx.bytes().all(|b| b.is_ascii_digit()));
+
+            let (idx, tail) = match index {
+                Some(index) => index,
+                None => {
+                    return Err(Error::SyntaxError {
+                        offset: idx_offset,
+                        reason: "malformed index in key",
+                    })
+                }
+            };
+
+            path.push(Cow::Borrowed(idx));
+            idx_offset += idx.len() + 2;
+            rest = tail;
+        }
+
+        offset += segment.len() + separator.len_utf8();
+    }
+
+    Ok(path)
+}
+
+// The raw text of a value as it appeared in the input, without surrounding quotes.
+#[derive(Clone)]
+struct Val<'de> {
+    raw: Cow<'de, str>,
+    quoted: bool,
+}
+
+impl<'de> Val<'de> {
+    fn from_token(token: &Token<'de>) -> Self {
+        let text = token.text;
+
+        if token.is_quoted() {
+            Val {
+                raw: Cow::Borrowed(&text[1..text.len() - 1]),
+                quoted: true,
+            }
+        } else {
+            Val {
+                raw: Cow::Borrowed(text),
+                quoted: false,
+            }
+        }
+    }
+
+    // Returns the value with escape sequences resolved.
+    fn text(self) -> Cow<'de, str> {
+        if !self.quoted {
+            return self.raw;
+        }
+
+        match self.raw {
+            Cow::Borrowed(s) => unescape(s),
+            Cow::Owned(s) => Cow::Owned(unescape(&s).into_owned()),
+        }
+    }
+}
+
+// A key, split into its segments, and an optional value. Bare keys have no value.
+struct Entry<'de> {
+    path: Vec<Cow<'de, str>>,
+    val: Option<Val<'de>>,
+}
+
+// Forwards a top-level deserialize_* call to the value making up the entire input.
+macro_rules! forward_to_value {
+    ($($method:ident)*) => {
+        $(
+            #[inline]
+            fn $method<V>(self, visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                self.value()?.$method(visitor)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let entries = self.grouped_entries()?;
+        Group::new(&entries, self).deserialize_any(visitor)
+    }
+
+    forward_to_value! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_u128 deserialize_f32 deserialize_f64 deserialize_char deserialize_str
+        deserialize_string deserialize_bytes deserialize_byte_buf deserialize_unit
+        deserialize_identifier
+    }
+
+    #[inline]
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // `NullStyle::Omit` leaves nothing at all of a top-level `None`
+        if self.input.is_empty() {
+            return visitor.visit_none();
+        }
+
+        match self.value() {
+            Ok(val) if val.is_none() => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    #[inline]
+    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.value()?.deserialize_unit_struct(name, visitor)
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match name {
+            RECORD_TOKEN => {
+                let entries = self.entries()?;
+                Group::new(&entries, self).deserialize_newtype_struct(name, visitor)
+            }
+            VALUE_TOKEN => self.value()?.deserialize_newtype_struct(name, visitor),
+            _ => visitor.visit_newtype_struct(self),
+        }
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let entries = self.grouped_entries()?;
+        Group::new(&entries, self).deserialize_seq(visitor)
+    }
+
+    #[inline]
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    #[inline]
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let entries = self.grouped_entries()?;
+        Group::new(&entries, self).deserialize_map(visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let entries = self.grouped_entries()?;
+        Group::new(&entries, self).deserialize_struct(name, fields, visitor)
+    }
+
+    #[inline]
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // A unit variant is the whole input, a tagged variant is a group of pairs.
+        // A bare key may be either, as `E::A(true)` is written as `A`, so only
+        // input that doesn't start with a key is read as a single value.
+        let single_value = match Tokenizer::new(self.input).next() {
+            Some(Token {
+                kind: TokenKind::Error { offset, .. },
+                span,
+                ..
+            }) => offset == span.start,
+            _ => false,
+        };
+        if single_value {
+            return self.value()?.deserialize_enum(name, variants, visitor);
+        }
+
+        let entries = self.grouped_entries()?;
+        Group::new(&entries, self).deserialize_enum(name, variants, visitor)
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
+
+// A run of entries sharing the first `depth` segments of their paths.
+#[derive(Clone, Copy)]
+struct Group<'a, 'de> {
+    entries: &'a [Entry<'de>],
+    depth: usize,
+    config: &'a DeserializerConfig,
+}
+
+impl<'a, 'de> Group<'a, 'de> {
+    #[inline]
+    fn new(entries: &'a [Entry<'de>], de: &'a Deserializer<'de>) -> Self {
+        Group {
+            entries,
+            depth: 0,
+            config: &de.config,
+        }
+    }
+
+    // Returns the value if this group is a single entry with no segments left.
+    fn leaf(&self) -> Option<ValueDeserializer<'a, 'de>> {
+        match self.entries {
+            [entry] if entry.path.len() == self.depth => Some(ValueDeserializer {
+                val: entry.val.clone(),
+                inference: self.config.inference,
+                config: self.config,
+            }),
+            _ => None,
+        }
+    }
+
+    // Splits off the entries sharing their next segment with the first entry.
+    // Returns the segment, the entries under it, and the remaining entries.
+    #[allow(clippy::type_complexity)]
+    fn split_first(&self) -> Result<Option<(&'a Cow<'de, str>, Self, Self)>> {
+        let first = match self.entries.first() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        let key = match first.path.get(self.depth) {
+            Some(key) => key,
+            None => {
+                return Err(de::Error::custom(format_args!(
+                    "`{}` is used as both a value and a key prefix",
+                    first.path.join(".")
+                )))
+            }
+        };
+
+        let len = self
+            .entries
+            .iter()
+            .take_while(|entry| entry.path.get(self.depth) == Some(key))
+            .count();
+        let (head, tail) = self.entries.split_at(len);
+
+        Ok(Some((
+            key,
+            Group {
+                entries: head,
+                depth: self.depth + 1,
+                ..*self
+            },
+            Group {
+                entries: tail,
+                ..*self
+            },
+        )))
+    }
+
+    // Returns true if the segments under this group count up from zero.
+    fn is_seq(&self) -> bool {
+        let mut buf = itoa::Buffer::new();
+        let mut rest = *self;
+        let mut idx: usize = 0;
+
+        while let Ok(Some((key, _, tail))) = rest.split_first() {
+            if key != buf.format(idx) {
+                return false;
+            }
+
+            rest = tail;
+            idx += 1;
+        }
+
+        idx > 0 && rest.entries.is_empty()
+    }
+
+    // Returns the entries under the given segment, if any.
+    fn get(&self, key: &str) -> Result<Option<Self>> {
+        let mut rest = *self;
+
+        while let Some((k, head, tail)) = rest.split_first()? {
+            if k == key {
+                return Ok(Some(head));
+            }
+
+            rest = tail;
+        }
+
+        Ok(None)
+    }
+
+    // Returns true if any entry has `key` as its next segment.
+    fn contains_key(&self, key: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| entry.path.get(self.depth).is_some_and(|k| k == key))
+    }
+
+    #[inline]
+    fn access(self) -> GroupAccess<'a, 'de> {
+        GroupAccess {
+            group: self,
+            rest: self,
+            val: None,
+            idx: 0,
+            fields: [].iter(),
+            missing: None,
+        }
+    }
+}
+
+// Forwards a deserialize_* call to the value if the group is a leaf.
+macro_rules! forward_to_leaf {
+    ($($method:ident)*) => {
+        $(
+            #[inline]
+            fn $method<V>(self, visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                match self.leaf() {
+                    Some(val) => val.$method(visitor),
+                    None => Err(de::Error::invalid_type(Unexpected::Map, &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Group<'_, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if let Some(val) = self.leaf() {
+            val.deserialize_any(visitor)
+        } else if self.is_seq() {
+            visitor.visit_seq(self.access())
+        } else {
+            visitor.visit_map(self.access())
+        }
+    }
+
+    forward_to_leaf! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_i128 deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64
+        deserialize_u128 deserialize_f32 deserialize_f64 deserialize_char deserialize_str
+        deserialize_string deserialize_bytes deserialize_byte_buf deserialize_unit
+        deserialize_identifier
+    }
+
+    #[inline]
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.leaf() {
+            Some(val) => val.deserialize_option(visitor),
+            None => visitor.visit_some(self),
+        }
+    }
+
+    #[inline]
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if name == RECORD_TOKEN {
+            return visitor.visit_map(FlatAccess {
+                entries: self.entries.iter(),
+                depth: self.depth,
+                config: self.config,
+                val: None,
+            });
+        }
+
+        match self.leaf() {
+            Some(val) => val.deserialize_newtype_struct(name, visitor),
+            None => visitor.visit_newtype_struct(self),
+        }
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.leaf() {
+            Some(val) => val.deserialize_seq(visitor),
+            None => visitor.visit_seq(self.access()),
+        }
+    }
+
+    #[inline]
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    #[inline]
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.leaf() {
+            Some(val) => val.deserialize_map(visitor),
+            None => visitor.visit_map(self.access()),
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if let Some(val) = self.leaf() {
+            return val.deserialize_map(visitor);
+        }
+
+        let mut access = self.access();
+        if self.config.missing_bools_false {
+            access.fields = fields.iter();
+        }
+
+        visitor.visit_map(access)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if let Some(val) = self.leaf() {
+            return val.deserialize_enum(name, variants, visitor);
+        }
+
+        if let TagStyle::Adjacent { tag, content } = &self.config.tagging {
+            if let Some(variant) = self.get(tag)?.and_then(|group| group.leaf()) {
+                return visitor.visit_enum(GroupVariant {
+                    variant,
+                    content: self.get(content)?,
+                });
+            }
+        }
+
+        // Externally tagged, i.e. a single segment naming the variant
+        match self.split_first()? {
+            Some((key, head, tail)) if tail.entries.is_empty() => {
+                let variant = match find_variant(name, variants, key) {
+                    Some(variant) => Cow::Borrowed(variant),
+                    None => key.clone(),
+                };
+
+                visitor.visit_enum(GroupVariant {
+                    variant: ValueDeserializer::key(variant, self.config),
+                    content: Some(head),
+                })
+            }
+            _ => Err(de::Error::invalid_type(Unexpected::Map, &visitor)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
+
+// Walks the groups beneath a group, either as map entries or as sequence elements.
+// When reading a struct, the fields missing from the group can be yielded at the end.
+struct GroupAccess<'a, 'de> {
+    group: Group<'a, 'de>,
+    rest: Group<'a, 'de>,
+    val: Option<Group<'a, 'de>>,
+    idx: usize,
+    fields: std::slice::Iter<'static, &'static str>,
+    missing: Option<&'static str>,
+}
+
+impl<'de> MapAccess<'de> for GroupAccess<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.rest.split_first()? {
+            Some((key, head, tail)) => {
+                self.rest = tail;
+                self.val = Some(head);
+                seed.deserialize(ValueDeserializer::key(key.clone(), self.group.config))
+                    .map(Some)
+            }
+            None => {
+                let group = self.group;
+                match self.fields.find(|field| !group.contains_key(field)) {
+                    Some(field) => {
+                        self.missing = Some(field);
+                        seed.deserialize(ValueDeserializer::key(Cow::Borrowed(field), group.config))
+                            .map(Some)
+                    }
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        if let Some(field) = self.missing.take() {
+            return seed.deserialize(Missing::Field(field));
+        }
+
+        let val = self
+            .val
+            .take()
+            .expect("next_value_seed() called before next_key_seed()");
+
+        seed.deserialize(val)
+    }
+}
+
+impl<'de> SeqAccess<'de> for GroupAccess<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let (key, head, tail) = match self.rest.split_first()? {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+
+        let mut buf = itoa::Buffer::new();
+        if key != buf.format(self.idx) {
+            // Elements written with `NullStyle::Omit` leave gaps in the indices
+            return match key.parse::<usize>() {
+                Ok(idx) if idx > self.idx && is_integer(key) => {
+                    self.idx += 1;
+                    seed.deserialize(Missing::Element(self.idx - 1)).map(Some)
+                }
+                _ => Err(de::Error::custom(format_args!(
+                    "expected sequence index {}, found `{}`",
+                    self.idx, key
+                ))),
+            };
+        }
+
+        self.rest = tail;
+        self.idx += 1;
+        seed.deserialize(head).map(Some)
+    }
+}
+
+// A tagged enum variant and the group holding its content, if any.
+struct GroupVariant<'a, 'de> {
+    variant: ValueDeserializer<'a, 'de>,
+    content: Option<Group<'a, 'de>>,
+}
+
+impl<'a, 'de> EnumAccess<'de> for GroupVariant<'a, 'de> {
+    type Error = Error;
+    type Variant = GroupContent<'a, 'de>;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(self.variant)?;
+        Ok((variant, GroupContent(self.content)))
+    }
+}
+
+struct GroupContent<'a, 'de>(Option<Group<'a, 'de>>);
+
+impl<'a, 'de> GroupContent<'a, 'de> {
+    fn content(self, exp: &dyn de::Expected) -> Result<Group<'a, 'de>> {
+        self.0
+            .ok_or_else(|| de::Error::invalid_type(Unexpected::UnitVariant, exp))
+    }
+}
+
+impl<'de> VariantAccess<'de> for GroupContent<'_, 'de> {
+    type Error = Error;
+
+    #[inline]
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        let content = self.content(&"newtype variant")?;
+        seed.deserialize(content)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let content = self.content(&visitor)?;
+        de::Deserializer::deserialize_seq(content, visitor)
+    }
+
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let content = self.content(&visitor)?;
+        de::Deserializer::deserialize_struct(content, "", fields, visitor)
+    }
+}
+
+// Walks the entries beneath a group without grouping them, yielding the rest of
+// each key as a whole. This is how a `Record` is deserialized.
+struct FlatAccess<'a, 'de> {
+    entries: std::slice::Iter<'a, Entry<'de>>,
+    depth: usize,
+    config: &'a DeserializerConfig,
+    val: Option<&'a Entry<'de>>,
+}
+
+impl<'de> MapAccess<'de> for FlatAccess<'_, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        let entry = match self.entries.next() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        let key = match &entry.path[self.depth..] {
+            [segment] if !segment.contains(['.', '%']) => segment.clone(),
+            segments => Cow::Owned(
+                segments
+                    .iter()
+                    .map(|segment| escape_segment(segment))
+                    .collect::<Vec<_>>()
+                    .join("."),
+            ),
+        };
+
+        self.val = Some(entry);
+        seed.deserialize(ValueDeserializer::key(key, self.config))
+            .map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let entry = self
+            .val
+            .take()
+            .expect("next_value_seed() called before next_key_seed()");
+
+        seed.deserialize(ValueDeserializer {
+            val: entry.val.clone(),
+            inference: self.config.inference,
+            config: self.config,
+        })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+// Escapes the '.'s and '%'s within a segment of a record key, which the
+// serializer percent-decodes after splitting the key on '.'.
+fn escape_segment(segment: &str) -> Cow<'_, str> {
+    if !segment.contains(['.', '%']) {
+        return Cow::Borrowed(segment);
+    }
+
+    Cow::Owned(segment.replace('%', "%25").replace('.', "%2E"))
+}
+
+// Stands in for a struct field missing from the input or a sequence element left
+// out of it. Bools are read as false, options as None and units as (). Missing
+// fields of any other type are read as their empty value, such as 0, "" or an
+// empty collection, since their key has already been handed out; anything else is
+// a missing field or element error.
+enum Missing {
+    Field(&'static str),
+    Element(usize),
+}
+
+impl Missing {
+    fn error(self) -> Error {
+        match self {
+            Missing::Field(field) => de::Error::missing_field(field),
+            Missing::Element(idx) => {
+                de::Error::custom(format_args!("missing sequence element {idx}"))
+            }
+        }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Missing {
+    type Deserializer = Self;
+
+    #[inline]
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! missing_as {
+    ($($method:ident => $visit:ident($val:expr),)*) => {
+        $(
+            #[inline]
+            fn $method<V>(self, visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                match self {
+                    Missing::Field(_) => visitor.$visit($val),
+                    Missing::Element(_) => Err(self.error()),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Missing {
+    type Error = Error;
+
+    #[inline]
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(self.error())
+    }
+
+    #[inline]
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_bool(false)
+    }
+
+    #[inline]
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_none()
+    }
+
+    #[inline]
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    #[inline]
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    missing_as! {
+        deserialize_i8 => visit_i8(0),
+        deserialize_i16 => visit_i16(0),
+        deserialize_i32 => visit_i32(0),
+        deserialize_i64 => visit_i64(0),
+        deserialize_i128 => visit_i128(0),
+        deserialize_u8 => visit_u8(0),
+        deserialize_u16 => visit_u16(0),
+        deserialize_u32 => visit_u32(0),
+        deserialize_u64 => visit_u64(0),
+        deserialize_u128 => visit_u128(0),
+        deserialize_f32 => visit_f32(0.0),
+        deserialize_f64 => visit_f64(0.0),
+        deserialize_str => visit_borrowed_str(""),
+        deserialize_string => visit_borrowed_str(""),
+        deserialize_bytes => visit_borrowed_bytes(b""),
+        deserialize_byte_buf => visit_borrowed_bytes(b""),
+        deserialize_seq => visit_seq(SeqDeserializer::new(std::iter::empty::<()>())),
+        deserialize_map => visit_map(MapDeserializer::new(std::iter::empty::<((), ())>())),
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Missing::Field(_) => visitor.visit_newtype_struct(self),
+            Missing::Element(_) => Err(self.error()),
+        }
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Missing::Field(field) => visitor.visit_seq(SeqDeserializer::new(
+                std::iter::repeat_with(|| Missing::Field(field)).take(len),
+            )),
+            Missing::Element(_) => Err(self.error()),
+        }
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self {
+            Missing::Field(_) => visitor.visit_map(MapDeserializer::new(
+                fields.iter().map(|field| (*field, Missing::Field(field))),
+            )),
+            Missing::Element(_) => Err(self.error()),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        char enum identifier
+    }
+}
+
+/// Deserializes a single value. A value of `None` represents a bare key.
+struct ValueDeserializer<'a, 'de> {
+    val: Option<Val<'de>>,
+    inference: Inference,
+    config: &'a DeserializerConfig,
+}
+
+impl<'a, 'de> ValueDeserializer<'a, 'de> {
+    // Returns true if the value is the placeholder for an empty container,
+    // i.e. the given brackets or nothing at all.
+    fn is_empty_container(&self, brackets: &str) -> bool {
+        matches!(&self.val, Some(Val { raw, quoted: false }) if raw.is_empty() || raw == brackets)
+    }
+
+    #[inline]
+    fn new(val: Val<'de>, config: &'a DeserializerConfig) -> Self {
+        ValueDeserializer {
+            val: Some(val),
+            inference: config.inference,
+            config,
+        }
+    }
+
+    // Keys are always strings, whatever they look like.
+    #[inline]
+    fn key(key: Cow<'de, str>, config: &'a DeserializerConfig) -> Self {
+        ValueDeserializer {
+            val: Some(Val {
+                raw: key,
+                quoted: false,
+            }),
+            inference: Inference::Strings,
+            config,
+        }
+    }
+
+    // Returns the text of the value, or an error if this is a bare key.
+    fn text(self, exp: &dyn de::Expected) -> Result<Cow<'de, str>> {
+        match self.val {
+            Some(val) => Ok(val.text()),
+            None => Err(de::Error::invalid_type(Unexpected::Bool(true), exp)),
+        }
+    }
+
+    #[inline]
+    fn is_unquoted(&self, s: &str) -> bool {
+        matches!(&self.val, Some(Val { raw, quoted: false }) if raw == s)
+    }
+
+    // Returns true if the value is written in the given style. Omitted values
+    // leave nothing to match.
+    fn is_null(&self, style: &NullStyle) -> bool {
+        match style {
+            NullStyle::Omit => false,
+            NullStyle::Empty => self.is_unquoted(""),
+            NullStyle::Null => self.is_unquoted("null"),
+            NullStyle::Token(token) => {
+                matches!(&self.val, Some(val) if val.clone().text() == token.as_str())
+            }
+        }
+    }
+
+    // `null` is read as `None` whatever the config says.
+    #[inline]
+    fn is_none(&self) -> bool {
+        self.is_unquoted("null") || self.is_null(&self.config.none)
+    }
+}
+
+// Finds the variant of the enum `name` written as `s`, accepting any of the
+// styles the serializer can write it in.
+fn find_variant(name: &str, variants: &'static [&'static str], s: &str) -> Option<&'static str> {
+    let variant = s
+        .strip_prefix(name)
+        .and_then(|s| s.strip_prefix("::"))
+        .unwrap_or(s);
+
+    variants
+        .iter()
+        .find(|v| **v == variant)
+        .or_else(|| {
+            is_integer(variant)
+                .then(|| variant.parse::<usize>().ok())
+                .flatten()
+                .and_then(|idx| variants.get(idx))
+        })
+        .or_else(|| {
+            variants
+                .iter()
+                .find(|v| v.to_lowercase() == variant || to_snake_case(v) == variant)
+        })
+        .copied()
+}
+
+// Returns true if `s` is an integer as formatted by itoa.
+fn is_integer(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+
+    !digits.is_empty()
+        && digits.bytes().all(|b| b.is_ascii_digit())
+        && (digits.len() == 1 || !digits.starts_with('0'))
+}
+
+// Returns true if `s` is a float as formatted by dtoa.
+fn is_float(s: &str) -> bool {
+    if matches!(s, "NaN" | "inf" | "-inf") {
+        return true;
+    }
+
+    let (mantissa, exponent) = match s.split_once('e') {
+        Some((m, e)) => (m, Some(e)),
+        None => (s, None),
+    };
+    let (int, frac) = match mantissa.split_once('.') {
+        Some((i, f)) => (i, Some(f)),
+        None => (mantissa, None),
+    };
+
+    let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+    is_integer(int)
+        && (frac.is_some() || exponent.is_some())
+        && frac.map_or(true, all_digits)
+        && exponent.map_or(true, |e| all_digits(e.strip_prefix('-').unwrap_or(e)))
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                let s = self.text(&visitor)?;
+                match s.parse() {
+                    Ok(v) => visitor.$visit(v),
+                    Err(_) => Err(de::Error::invalid_value(Unexpected::Str(&s), &visitor)),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'_, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let raw = match &self.val {
+            None => return visitor.visit_bool(true),
+            Some(Val { quoted: true, .. }) => return self.deserialize_str(visitor),
+            Some(Val { raw, .. }) => raw,
+        };
+
+        if self.inference == Inference::Strings {
+            return self.deserialize_str(visitor);
+        } else if self.is_none() {
+            return visitor.visit_none();
+        }
+
+        match &**raw {
+            "null" => return visitor.visit_none(),
+            "true" => return visitor.visit_bool(true),
+            "false" => return visitor.visit_bool(false),
+            _ => (),
+        }
+
+        if is_integer(raw) {
+            if let Ok(v) = raw.parse::<i64>() {
+                return visitor.visit_i64(v);
+            } else if let Ok(v) = raw.parse::<u64>() {
+                return visitor.visit_u64(v);
+            } else if let Ok(v) = raw.parse::<i128>() {
+                return visitor.visit_i128(v);
+            } else if let Ok(v) = raw.parse::<u128>() {
+                return visitor.visit_u128(v);
+            }
+        } else if is_float(raw) {
+            if let Ok(v) = raw.parse::<f64>() {
+                return visitor.visit_f64(v);
+            }
+        }
+
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.val.is_none() {
+            return visitor.visit_bool(true);
+        }
+
+        let s = self.text(&visitor)?;
+        match &*s {
+            "true" => visitor.visit_bool(true),
+            "false" => visitor.visit_bool(false),
+            _ => Err(de::Error::invalid_value(Unexpected::Str(&s), &visitor)),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let s = self.text(&visitor)?;
+        let mut chars = s.chars();
+
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => visitor.visit_char(ch),
+            _ => Err(de::Error::invalid_value(Unexpected::Str(&s), &visitor)),
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.text(&visitor)? {
+            Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+            Cow::Owned(s) => visitor.visit_string(s),
+        }
+    }
+
+    #[inline]
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    // Bytes are written as hex by the serializer
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let style = self.config.bytes;
+        let s = self.text(&visitor)?;
+
+        let bytes = match style {
+            BytesStyle::UpperHex | BytesStyle::LowerHex => base16::decode(s.as_bytes()).ok(),
+            BytesStyle::Base64 => decode_base64(s.as_bytes(), BASE64),
+            BytesStyle::Base64Url => decode_base64(s.as_bytes(), BASE64_URL),
+            BytesStyle::Utf8 => {
+                return match s {
+                    Cow::Borrowed(s) => visitor.visit_borrowed_bytes(s.as_bytes()),
+                    Cow::Owned(s) => visitor.visit_byte_buf(s.into_bytes()),
+                }
+            }
+        };
+
+        match bytes {
+            Some(v) => visitor.visit_byte_buf(v),
+            None => Err(de::Error::invalid_value(Unexpected::Str(&s), &visitor)),
+        }
+    }
+
+    #[inline]
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    #[inline]
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.is_none() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    // Unit is written as an empty value unless the config says otherwise
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.is_unquoted("") || self.is_null(&self.config.unit) {
+            return visitor.visit_unit();
+        }
+
+        let s = self.text(&visitor)?;
+        Err(de::Error::invalid_type(Unexpected::Str(&s), &visitor))
+    }
+
+    #[inline]
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    #[inline]
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if name != VALUE_TOKEN {
+            return visitor.visit_newtype_struct(self);
+        }
+
+        if self.val.is_none() {
+            visitor.visit_map(MapDeserializer::new(std::iter::once((BARE_TOKEN, ()))))
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.is_empty_container("[]") {
+            return visitor.visit_seq(SeqDeserializer::new(std::iter::empty::<()>()));
+        }
+
+        let s = self.text(&visitor)?;
+        Err(de::Error::invalid_type(Unexpected::Str(&s), &visitor))
+    }
+
+    #[inline]
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    #[inline]
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.is_empty_container("{}") {
+            return visitor.visit_map(MapDeserializer::new(std::iter::empty::<((), ())>()));
+        }
+
+        let s = self.text(&visitor)?;
+        Err(de::Error::invalid_type(Unexpected::Str(&s), &visitor))
+    }
+
+    #[inline]
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_map(visitor)
+    }
+
+    // Unit variants are written as Enum::Variant
+    fn deserialize_enum<V>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let s = self.text(&visitor)?;
+        let variant = find_variant(name, variants, &s).unwrap_or(&s);
+
+        visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(variant))
+    }
+
+    #[inline]
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    #[inline]
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_float, is_integer, Deserializer, Entry, Recovery};
+    use crate::config::IndexStyle;
+    use crate::error::{Error, Result};
+    use crate::{Record, Serializer};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    fn pairs(s: &str) -> Result<Vec<(String, Option<String>)>> {
+        Ok(Deserializer::from_str(s)
+            .entries()?
+            .into_iter()
+            .map(|Entry { path, val }| (path.join("."), val.map(|v| v.text().into_owned())))
+            .collect())
+    }
+
+    #[test]
+    fn test_pairs() {
+        assert_eq!(pairs("").unwrap(), vec![]);
+        assert_eq!(
+            pairs("a=1 b c=\"hello world\" d=").unwrap(),
+            vec![
+                ("a".to_owned(), Some("1".to_owned())),
+                ("b".to_owned(), None),
+                ("c".to_owned(), Some("hello world".to_owned())),
+                ("d".to_owned(), Some("".to_owned())),
+            ]
+        );
+        assert_eq!(
+            pairs("nums.0=1 my_map.33").unwrap(),
+            vec![
+                ("nums.0".to_owned(), Some("1".to_owned())),
+                ("my_map.33".to_owned(), None),
+            ]
+        );
+        assert_eq!(
+            pairs("has%20spaces=\"a \\\"quoted\\\" \\\\ value\"\n").unwrap(),
+            vec![(
+                "has spaces".to_owned(),
+                Some("a \"quoted\" \\ value".to_owned())
+            )]
+        );
+
+        assert!(matches!(
+            pairs("a=\"unterminated"),
+            Err(Error::SyntaxError { offset: 2, .. })
+        ));
+        assert!(matches!(
+            pairs("a=\"b\"c"),
+            Err(Error::SyntaxError { offset: 5, .. })
+        ));
+        assert!(matches!(
+            pairs("a= =b"),
+            Err(Error::SyntaxError { offset: 3, .. })
+        ));
+        assert!(matches!(
+            pairs("a=1 b..c=2"),
+            Err(Error::SyntaxError { offset: 6, .. })
+        ));
+    }
+
+    #[test]
+    fn test_infer_numbers() {
+        for s in [
+            "0",
+            "7",
+            "-42",
+            "1690232215",
+            "340282366920938463463374607431768211455",
+        ] {
+            assert!(is_integer(s), "{s}");
+        }
+        for s in ["", "-", "02134", "+1", "1.0", "1_000", "0x10"] {
+            assert!(!is_integer(s), "{s}");
+        }
+
+        for s in [
+            "0.0", "1.5", "-0.25", "1e20", "1.5e-7", "NaN", "inf", "-inf",
+        ] {
+            assert!(is_float(s), "{s}");
+        }
+        for s in [
+            "1", "1.", ".5", "01.5", "1e", "1e+5", "nan", "Infinity", "1.2.3",
+        ] {
+            assert!(!is_float(s), "{s}");
+        }
+    }
+
+    #[test]
+    fn test_recovery() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Line {
+            a: u8,
+            c: Option<String>,
+            _unparsed: Option<String>,
+        }
+
+        let s = "a=1 b=\"x\"y c=ok =d e..f=2 g=\"trunc";
+
+        let mut de = Deserializer::from_str(s);
+        match Line::deserialize(&mut de) {
+            Err(Error::SyntaxError { offset, reason }) => {
+                assert_eq!(offset, 9);
+                assert_eq!(reason, "expected a space between pairs");
+            }
+            r => panic!("unexpected result {r:?}"),
+        }
+
+        let mut de = Deserializer::builder()
+            .with_recovery(Recovery::Skip)
+            .build(s);
+        assert_eq!(
+            Line::deserialize(&mut de).unwrap(),
+            Line {
+                a: 1,
+                c: Some("ok".to_owned()),
+                _unparsed: None,
+            }
+        );
+
+        let mut de = Deserializer::builder()
+            .with_recovery(Recovery::Capture("_unparsed".to_owned()))
+            .build(s);
+        assert_eq!(
+            Line::deserialize(&mut de).unwrap(),
+            Line {
+                a: 1,
+                c: Some("ok".to_owned()),
+                _unparsed: Some("b=\"x\"y =d e..f=2 g=\"trunc".to_owned()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_missing_bools() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Flags {
+            a: bool,
+            #[serde(rename = "B")]
+            b: bool,
+            c: Option<u8>,
+            inner: Inner,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Inner {
+            id: u8,
+            d: bool,
+        }
+
+        let flags = Flags {
+            a: true,
+            b: false,
+            c: None,
+            inner: Inner { id: 3, d: false },
+        };
+        let s = crate::to_string(&flags).unwrap();
+        assert_eq!(s, "a c=null inner.id=3");
+
+        // missing bools are only read as false when asked to
+        assert!(crate::from_str::<Flags>(&s).is_err());
+
+        let builder = Deserializer::builder().with_missing_bools_false(true);
+        let read = Flags::deserialize(&mut builder.clone().build(&s)).unwrap();
+        assert_eq!(read, flags);
+
+        // explicit values are read either way
+        let read: Flags = crate::from_str("a=false B inner.id=3 inner.d=false").unwrap();
+        assert!(!read.a && read.b && !read.inner.d);
+
+        // other missing fields are read as their empty value
+        let read = Flags::deserialize(&mut builder.clone().build("a B")).unwrap();
+        assert_eq!(read.inner, Inner { id: 0, d: false });
+
+        // fields of the same name in different variants are independent
+        #[derive(Deserialize, Debug, PartialEq)]
+        enum Event {
+            On { id: u8, flag: bool },
+            Off { flag: u8 },
+        }
+
+        let read = Event::deserialize(&mut builder.clone().build("On.id=1")).unwrap();
+        assert_eq!(read, Event::On { id: 1, flag: false });
+        let read = Event::deserialize(&mut builder.build("Off.flag=2")).unwrap();
+        assert_eq!(read, Event::Off { flag: 2 });
+
+        // without it, other missing fields keep their defaults
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Defaults {
+            #[serde(default)]
+            a: bool,
+            #[serde(default)]
+            n: u32,
+            #[serde(default)]
+            tags: Vec<String>,
+        }
+
+        let read: Defaults = crate::from_str("a").unwrap();
+        assert_eq!(
+            read,
+            Defaults {
+                a: true,
+                n: 0,
+                tags: vec![],
+            }
+        );
+
+        let mut reader = crate::Reader::new("n=1\na\n".as_bytes());
+        assert_eq!(reader.read::<Defaults>().unwrap().unwrap().n, 1);
+        assert!(reader.read::<Defaults>().unwrap().unwrap().a);
+
+        // with it, missing fields that aren't bools are still read
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Mixed {
+            flag: bool,
+            #[serde(default)]
+            n: u32,
+            #[serde(default)]
+            name: String,
+            #[serde(default)]
+            tags: Vec<String>,
+            #[serde(default)]
+            pair: (u8, Option<u8>),
+        }
+
+        let builder = Deserializer::builder().with_missing_bools_false(true);
+        let read = Mixed::deserialize(&mut builder.clone().build("n=3")).unwrap();
+        assert_eq!(
+            read,
+            Mixed {
+                flag: false,
+                n: 3,
+                name: String::new(),
+                tags: vec![],
+                pair: (0, None),
+            }
+        );
+        let read = Mixed::deserialize(&mut builder.build("flag tags.0=x")).unwrap();
+        assert!(read.flag && read.n == 0 && read.tags == ["x"]);
+        assert!(crate::from_str::<Mixed>("n=3").is_err());
+        assert_eq!(crate::from_str::<Mixed>("flag").unwrap().n, 0);
+    }
+
+    #[test]
+    fn test_key_paths() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Doc {
+            nums: Vec<u8>,
+            items: Vec<Item>,
+            map: BTreeMap<String, u8>,
+        }
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Item {
+            id: u8,
+            pair: (u8, u8),
+        }
+
+        let doc = Doc {
+            nums: vec![1, 2],
+            items: vec![Item {
+                id: 3,
+                pair: (4, 5),
+            }],
+            map: [("a.b".to_owned(), 6), ("c_d[0]".to_owned(), 7)]
+                .into_iter()
+                .collect(),
+        };
+
+        for (separator, indices, expected) in [
+            (
+                '.',
+                IndexStyle::Separated,
+                "nums.0=1 nums.1=2 items.0.id=3 items.0.pair.0=4 items.0.pair.1=5 \
+                map.a%2Eb=6 map.c_d[0]=7",
+            ),
+            (
+                '_',
+                IndexStyle::Separated,
+                "nums_0=1 nums_1=2 items_0_id=3 items_0_pair_0=4 items_0_pair_1=5 \
+                map_a.b=6 map_c%5Fd[0]=7",
+            ),
+            (
+                '/',
+                IndexStyle::Brackets,
+                "nums[0]=1 nums[1]=2 items[0]/id=3 items[0]/pair[0]=4 items[0]/pair[1]=5 \
+                map/a.b=6 map/c_d%5B0%5D=7",
+            ),
+        ] {
+            let mut ser = Serializer::builder()
+                .with_separator(separator)
+                .with_indices(indices)
+                .build(Vec::new());
+            doc.serialize(&mut ser).unwrap();
+            let s = String::from_utf8(ser.writer()).unwrap();
+            assert_eq!(s, expected);
+
+            let mut de = Deserializer::builder()
+                .with_separator(separator)
+                .with_indices(indices)
+                .build(&s);
+            assert_eq!(Doc::deserialize(&mut de).unwrap(), doc);
+
+            // records hold dotted keys and write them back in the same style
+            let mut de = Deserializer::builder()
+                .with_separator(separator)
+                .with_indices(indices)
+                .build(&s);
+            let record = Record::deserialize(&mut de).unwrap();
+            assert_eq!(record.iter().nth(2).map(|(k, _)| k), Some("items.0.id"));
+
+            let mut ser = Serializer::builder()
+                .with_separator(separator)
+                .with_indices(indices)
+                .build(Vec::new());
+            record.serialize(&mut ser).unwrap();
+            assert_eq!(ser.writer(), s.as_bytes());
+        }
+
+        // separators and percent signs within a segment survive a round trip
+        let s = "map.a%2Eb=1 map.c%25d=2 e%2E0%2525=3";
+        let record: Record = crate::from_str(s).unwrap();
+        assert_eq!(
+            record.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            ["map.a%2Eb", "map.c%25d", "e%2E0%2525"]
+        );
+        assert_eq!(crate::to_string(&record).unwrap(), s);
+
+        for (input, offset) in [("a[=1", 1), ("a[x]=1", 1), ("a[]=1", 1), ("a[0]b=1", 4)] {
+            let mut de = Deserializer::builder()
+                .with_indices(IndexStyle::Brackets)
+                .build(input);
+            assert!(matches!(
+                Record::deserialize(&mut de),
+                Err(Error::SyntaxError {
+                    offset: o,
+                    reason: "malformed index in key"
+                }) if o == offset
+            ));
+        }
+    }
+
+    #[test]
+    fn test_interleaved_keys() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Out {
+            a: In,
+            b: u8,
+        }
+
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct In {
+            x: u8,
+            y: u8,
+        }
+
+        let out: Out = crate::from_str("a.x=1 b=2 a.y=3").unwrap();
+        assert_eq!(
+            out,
+            Out {
+                a: In { x: 1, y: 3 },
+                b: 2
+            }
+        );
+
+        let map: BTreeMap<String, Vec<u8>> = crate::from_str("n.0=1 m.0=5 n.1=2").unwrap();
+        assert_eq!(map["n"], [1, 2]);
+        assert_eq!(map["m"], [5]);
+
+        // a value and a key prefix still conflict when apart
+        assert!(crate::from_str::<Out>("a=1 b=2 a.y=3").is_err());
+
+        // records keep the pairs in their original order
+        let record: Record = crate::from_str("a.x=1 b=2 a.y=3").unwrap();
+        assert_eq!(crate::to_string(&record).unwrap(), "a.x=1 b=2 a.y=3");
+    }
+}
@@ -12,16 +12,32 @@ use serde::ser::{
     self, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
     SerializeTupleStruct, SerializeTupleVariant,
 };
+use std::borrow::Cow;
+use std::fmt::{Display, LowerExp};
 use std::io::Write;
-
+use std::mem;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::config::{
+    BoolStyle, BytesStyle, ControlStyle, EmptyContainerStyle, EmptyKeyStyle, EscapeStyle,
+    FloatStyle, IndexStyle, KeyStyle, NonFiniteStyle, NullStyle, SeqStyle, SerializerBuilder,
+    SerializerConfig, TagStyle, VariantStyle,
+};
 use crate::error::{Error, Result};
-use crate::util::as_control_picture;
+use crate::lex::percent_decode;
+use crate::util::{
+    as_control_picture, is_deceptive, is_format, to_snake_case, valid_in_ident, write_base64,
+    write_hex, BASE64, BASE64_URL, HEX_LOWER, HEX_UPPER,
+};
+use crate::value::{BARE_TOKEN, BOOL_TOKEN, RECORD_TOKEN, STRING_TOKEN};
 
 /// Provides a serde Serializer implementation that is roughly compatible with
 /// <https://pkg.go.dev/github.com/kr/logfmt>
 ///
 /// The `Serializer` is not particularly expensive to construct, so calling `to_writer`
-/// should be fine normally. If you'd like to re-use the `Serializer`, you must call
+/// should be fine normally. Use `Serializer::builder()` to change how values are
+/// written. If you'd like to re-use the `Serializer`, you must call
 /// `serializer.reset()` or `serializer.next()` to reset the serializer's internal state such
 /// that it is ready for the next document.
 ///
@@ -35,7 +51,7 @@ use crate::util::as_control_picture;
 ///    pub ts: u64,
 ///    pub message: String,
 /// }
-
+///
 /// fn main() -> Result<()> {
 ///    let s = MyStruct{
 ///        ts: 1690232215,
@@ -64,8 +80,94 @@ use crate::util::as_control_picture;
 /// ```
 pub struct Serializer<B> {
     w: B,
-    ns: Vec<String>,
+    ns: Vec<Segment>,
     have_written: bool,
+    mode: Mode,
+    written: Written,
+    skip_value: bool,
+    starts: Vec<usize>,
+    #[cfg(feature = "json")]
+    json_config: Option<Arc<SerializerConfig>>,
+    config: Arc<SerializerConfig>,
+}
+
+// How the value being serialized is written. Values that are written unlike
+// the ones around them swap in a `Mode` of their own with `with_mode`.
+#[derive(Clone, Copy, Default)]
+struct Mode {
+    // The keys of a `Record` are whole paths rather than single segments.
+    record_keys: bool,
+    // Bools are written in the given style, and nulls are written out, under
+    // the key at the given depth.
+    explicit: Option<(usize, BoolStyle)>,
+    // Strings are quoted whatever they hold, for `Value::String`.
+    quoted: bool,
+    // Values are written as they are, without quotes or escapes, for map keys.
+    raw: bool,
+    // The value at the given depth is an element of a compact sequence. Unless
+    // it turns out to be nested, it's written as it is, without its key, quotes
+    // or escapes, to be joined with the others and escaped as a whole.
+    element: Option<usize>,
+}
+
+// What has been written so far. Compact sequences look at it to tell what
+// their elements turned out to be.
+#[derive(Default)]
+struct Written {
+    // The number of pairs written, to tell whether a container was empty.
+    pairs: usize,
+    // Whether a struct, map, sequence or key was written, i.e. anything but a
+    // single value.
+    nested: bool,
+    // The last bool written.
+    last_bool: Option<bool>,
+    // Whether the last string written has to be quoted.
+    quoted: bool,
+}
+
+// A segment of the key being written.
+struct Segment {
+    name: String,
+    kind: SegmentKind,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SegmentKind {
+    // A struct field, map key or variant name.
+    Name,
+    // A sequence or tuple index.
+    Index,
+    // A key already joined with `.`, as held by a `Record`, with any `.` or `%`
+    // within a segment percent-escaped.
+    Path,
+}
+
+// Splits the segments of a key into the parts written between separators, and
+// whether each part is an index. Parts of a `Record` key that are numbers are
+// taken to be indices, and their escaped `.`s and `%`s are decoded.
+fn key_parts(ns: &[Segment]) -> impl Iterator<Item = (Cow<'_, str>, bool)> {
+    ns.iter().flat_map(|seg| {
+        let path = seg.kind == SegmentKind::Path;
+
+        seg.name
+            .split(move |c| path && c == '.')
+            .map(move |part| match seg.kind {
+                SegmentKind::Name => (Cow::Borrowed(part), false),
+                SegmentKind::Index => (Cow::Borrowed(part), true),
+                SegmentKind::Path => {
+                    let index = !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+                    (percent_decode(part).unwrap_or(Cow::Borrowed(part)), index)
+                }
+            })
+    })
+}
+
+impl Serializer<()> {
+    /// Returns a [`SerializerBuilder`] for configuring a new `Serializer`.
+    #[inline]
+    pub fn builder() -> SerializerBuilder {
+        SerializerBuilder::new()
+    }
 }
 
 impl<B> Serializer<B>
@@ -75,10 +177,27 @@ where
     /// Construct a new `LogfmtSerializer` that writes to
     /// the supplied object implementing `Write`.
     pub fn new(writer: B) -> Self {
+        Self::with_config(writer, SerializerConfig::default())
+    }
+
+    /// Construct a new `Serializer` that writes to the supplied object
+    /// implementing `Write` using the given configuration. Pass an
+    /// `Arc<SerializerConfig>` to share one configuration between serializers.
+    pub fn with_config<C>(writer: B, config: C) -> Self
+    where
+        C: Into<Arc<SerializerConfig>>,
+    {
         Serializer {
             w: writer,
             ns: Vec::with_capacity(8),
             have_written: false,
+            mode: Mode::default(),
+            written: Written::default(),
+            skip_value: false,
+            starts: Vec::new(),
+            #[cfg(feature = "json")]
+            json_config: None,
+            config: config.into(),
         }
     }
 
@@ -94,6 +213,7 @@ where
     /// This function will fail if the underlying IO object
     /// returns an error while writing to it.
     #[inline]
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Result<()> {
         self.w.write_all(b"\n")?;
         self.reset();
@@ -107,69 +227,163 @@ where
     pub fn reset(&mut self) {
         self.ns.clear();
         self.have_written = false;
+        self.mode = Mode::default();
+        self.written = Written::default();
+        self.skip_value = false;
+        self.starts.clear();
     }
 
     fn enter_ns<S>(&mut self, name: &S)
     where
         S: ToString,
     {
-        self.ns.push(name.to_string());
+        self.written.nested = true;
+        self.ns.push(Segment {
+            name: name.to_string(),
+            kind: SegmentKind::Name,
+        });
+    }
+
+    fn enter_index(&mut self, idx: usize) {
+        self.ns.push(Segment {
+            name: itoa::Buffer::new().format(idx).to_owned(),
+            kind: SegmentKind::Index,
+        });
+    }
+
+    // Enters the name space for a struct field or map key, applying the empty
+    // key style to each segment that nothing would be left of once written.
+    // Returns false without entering anything if the pair should be skipped.
+    fn enter_key(&mut self, key: &str) -> bool {
+        let config = &*self.config;
+        let path = self.mode.record_keys;
+        let segments = || key.split(move |c| path && c == '.');
+        let empty = |segment: &str| {
+            segment.is_empty()
+                || (config.keys == KeyStyle::Strip
+                    && !segment.chars().any(|ch| Self::valid_in_key(ch, config)))
+        };
+
+        let name = match &config.empty_keys {
+            _ if !segments().any(empty) => key.to_owned(),
+            EmptyKeyStyle::Placeholder(placeholder) => segments()
+                .map(|segment| match empty(segment) {
+                    true => placeholder.as_str(),
+                    false => segment,
+                })
+                .collect::<Vec<_>>()
+                .join("."),
+            EmptyKeyStyle::Skip => return false,
+            EmptyKeyStyle::Error => key.to_owned(),
+        };
+
+        let kind = if self.mode.record_keys {
+            SegmentKind::Path
+        } else {
+            SegmentKind::Name
+        };
+
+        self.ns.push(Segment { name, kind });
+        true
     }
 
     #[inline]
     fn leave_ns(&mut self) -> String {
         if let Some(ns) = self.ns.pop() {
-            ns
+            ns.name
         } else {
             panic!("leave_ns() called while in the top level name space");
         }
     }
 
-    // Returns true if the character is valid in logfmt identifiers
-    #[inline]
-    fn valid_in_ident(c: char) -> bool {
-        c > ' ' && c != '=' && c != '"' && !c.is_control()
-    }
-
     // Writes an identifier to the underlying stream.
     // If the identifier has zero-length, then this
-    // function returns an error. Invalid bytes are
-    // escaped.
-    fn write_ident(w: &mut B, ident: &str) -> Result<()> {
+    // function returns an error. Invalid characters are
+    // handled according to the key style.
+    fn write_ident(w: &mut B, config: &SerializerConfig, ident: &str) -> Result<()> {
         if ident.is_empty() {
-            return Err(Error::EmptyIdentifier);
+            return Err(Error::EmptyIdentifier {
+                key: ident.to_owned(),
+            });
+        }
+
+        let valid = |ch: char| Self::valid_in_key(ch, config);
+
+        match config.keys {
+            KeyStyle::Error if !ident.chars().all(valid) => {
+                return Err(Error::InvalidIdentifier {
+                    key: ident.to_owned(),
+                })
+            }
+            KeyStyle::Strip if !ident.chars().any(valid) => {
+                return Err(Error::EmptyIdentifier {
+                    key: ident.to_owned(),
+                })
+            }
+            _ => {}
         }
 
         let (mut beg, mut end): (usize, usize) = (0, 0);
+        let mut buf: [u8; 4] = [0; 4];
 
         for ch in ident.chars() {
-            if Self::valid_in_ident(ch) {
+            if valid(ch) {
                 end += ch.len_utf8();
             } else {
                 if end - beg > 0 {
-                    w.write_all(ident[beg..end].as_bytes())?;
+                    w.write_all(&ident.as_bytes()[beg..end])?;
                 }
 
-                let mut buf: [u8; 4] = [0; 4];
-                for b in ch.encode_utf8(&mut buf).as_bytes() {
-                    w.write_all(b"%")?;
-                    w.write_all(&base16::encode_byte_u(*b))?;
+                let out = match config.keys {
+                    KeyStyle::Replace(r) => Some(r),
+                    KeyStyle::Strip => None,
+                    _ => Some(ch),
+                };
+
+                match out {
+                    Some(out) if valid(out) => w.write_all(out.encode_utf8(&mut buf).as_bytes())?,
+                    Some(out) => {
+                        for b in out.encode_utf8(&mut buf).as_bytes() {
+                            w.write_all(b"%")?;
+                            w.write_all(&base16::encode_byte_u(*b))?;
+                        }
+                    }
+                    None => {}
                 }
+
                 end += ch.len_utf8();
                 beg = end;
             }
         }
 
         if end - beg > 0 {
-            w.write_all(ident[beg..end].as_bytes())?;
+            w.write_all(&ident.as_bytes()[beg..end])?;
         }
 
         Ok(())
     }
 
     #[inline]
-    fn valid_in_string(c: char) -> bool {
-        c >= ' ' && c != '"' && c != '\\' && c != '\x7F'
+    fn valid_in_key(c: char, config: &SerializerConfig) -> bool {
+        valid_in_ident(c)
+            && c != config.separator
+            && (c != '%' || config.keys != KeyStyle::Percent)
+            && (config.indices != IndexStyle::Brackets || (c != '[' && c != ']'))
+            && (c.is_ascii() || !config.ascii_only)
+            && !(config.hardened && is_deceptive(c))
+    }
+
+    #[inline]
+    fn valid_in_string(c: char, config: &SerializerConfig) -> bool {
+        c >= ' '
+            && c != '"'
+            && c != '\\'
+            && c != '\x7F'
+            && (c.is_ascii() || !config.ascii_only)
+            && !(config.hardened && (c.is_control() || is_deceptive(c)))
+            && (config.controls == ControlStyle::Pictures || !c.is_control())
+            && (config.controls != ControlStyle::Go
+                || ((c == ' ' || !c.is_whitespace()) && !is_format(c)))
     }
 
     #[inline]
@@ -184,25 +398,46 @@ where
             || c == 'u' // won't validate is valid unicode
     }
 
-    #[inline]
-    fn write_escape(dst: &mut B, c: char) -> Result<()> {
-        let mut buf: [u8; 4] = [0; 4];
-
-        let b = match c {
-            '\0' => b"\\0",
-            '\n' => b"\\n",
-            '\t' => b"\\t",
-            '\r' => b"\\r",
-            '\0'..='\x1F' | '\x7F' => as_control_picture(c)
-                .expect("function handles ascii codes [0x0, 0x1F] and 0x7F")
-                .encode_utf8(&mut buf)
-                .as_bytes(),
-            '\\' => b"\\\\",
-            '"' => b"\\\"",
-            _ => c.encode_utf8(&mut buf).as_bytes(),
+    fn write_escape(dst: &mut B, c: char, config: &SerializerConfig) -> Result<()> {
+        // control pictures can't be told apart from the characters they stand
+        // for, and aren't ASCII
+        let controls = match config.controls {
+            ControlStyle::Pictures if config.escapes == EscapeStyle::Lossless => ControlStyle::Hex,
+            ControlStyle::Pictures if config.ascii_only => ControlStyle::Hex,
+            controls => controls,
         };
 
-        dst.write_all(b)?;
+        let mut buf: [u8; 4] = [0; 4];
+        let code = c as u32;
+
+        match (c, controls) {
+            ('\n', _) => dst.write_all(b"\\n")?,
+            ('\t', _) => dst.write_all(b"\\t")?,
+            ('\r', _) => dst.write_all(b"\\r")?,
+            ('\\', _) => dst.write_all(b"\\\\")?,
+            ('"', _) => dst.write_all(b"\\\"")?,
+            ('\0', ControlStyle::Pictures | ControlStyle::Hex) => dst.write_all(b"\\0")?,
+            ('\x07', ControlStyle::Go) => dst.write_all(b"\\a")?,
+            ('\x08', ControlStyle::Go) => dst.write_all(b"\\b")?,
+            ('\x0B', ControlStyle::Go) => dst.write_all(b"\\v")?,
+            ('\x0C', ControlStyle::Go) => dst.write_all(b"\\f")?,
+            ('\0'..='\x7F', ControlStyle::Go) => write!(dst, "\\x{:02x}", code)?,
+            (_, ControlStyle::Go) if code > 0xFFFF => write!(dst, "\\U{:08x}", code)?,
+            (_, ControlStyle::Go) => write!(dst, "\\u{:04x}", code)?,
+            ('\0'..='\x1F' | '\x7F', ControlStyle::Pictures) => dst.write_all(
+                as_control_picture(c)
+                    .expect("function handles ascii codes [0x0, 0x1F] and 0x7F")
+                    .encode_utf8(&mut buf)
+                    .as_bytes(),
+            )?,
+            (_, ControlStyle::Hex) if c.is_control() && code < 0x100 => {
+                write!(dst, "\\x{:02x}", code)?
+            }
+            (_, ControlStyle::Unicode) if code < 0x10000 => write!(dst, "\\u{:04x}", code)?,
+            _ if !c.is_ascii() && code < 0x10000 => write!(dst, "\\u{:04x}", code)?,
+            _ if !c.is_ascii() => write!(dst, "\\u{{{:x}}}", code)?,
+            _ => dst.write_all(c.encode_utf8(&mut buf).as_bytes())?,
+        }
 
         Ok(())
     }
@@ -211,28 +446,31 @@ where
     // The value has one of four representations
     // 1) If the value is a valid identifier, the value is represented without quotes
     // 2) If the value is not a valid identifier or requires escapes, it is quoted
-    // and is escaped as necessary
+    // and is escaped as necessary. Existing escape sequences are passed through
+    // unless the escape style is lossless.
     // 3) If the value has zero length, nothing is written.
+    // Values that must read back as strings, i.e. `Value::String`, are always quoted.
     fn write_val(&mut self, val: &str) -> Result<()> {
-        if val.is_empty() {
+        let config = &*self.config;
+
+        // map keys are escaped by `write_ident` instead, and the elements of
+        // compact sequences once they're joined
+        if self.mode.raw || self.in_element() {
+            self.w.write_all(val.as_bytes())?;
             return Ok(());
         }
 
-        // if it's a valid ident, we can just write it as one
-        let is_ident = {
-            let mut ok = true;
-
-            for ch in val.chars() {
-                if !Self::valid_in_ident(ch) {
-                    ok = false;
-                    break;
-                }
-            }
+        if val.is_empty() && !self.mode.quoted {
+            return Ok(());
+        }
 
-            ok
-        };
-        if is_ident {
-            return Self::write_ident(&mut self.w, val);
+        // if it's a valid ident and needs no escapes, we can just write it as one
+        let is_ident = val
+            .chars()
+            .all(|ch| valid_in_ident(ch) && (ch == '\\' || Self::valid_in_string(ch, config)));
+        if is_ident && !self.mode.quoted {
+            self.w.write_all(val.as_bytes())?;
+            return Ok(());
         }
 
         // needs quotes
@@ -242,9 +480,10 @@ where
 
         let mut iter = val.chars().peekable();
         while let Some(ch) = iter.next() {
-            if Self::valid_in_string(ch) {
+            if Self::valid_in_string(ch, config) {
                 end += ch.len_utf8();
             } else if ch == '\\'
+                && config.escapes == EscapeStyle::PassThrough
                 && iter.peek().is_some()
                 && Self::is_valid_escape(*iter.peek().unwrap())
             {
@@ -253,17 +492,17 @@ where
                 let _ = iter.next();
             } else {
                 if end - beg > 0 {
-                    self.w.write_all(val[beg..end].as_bytes())?;
+                    self.w.write_all(&val.as_bytes()[beg..end])?;
                 }
 
-                Self::write_escape(&mut self.w, ch)?;
+                Self::write_escape(&mut self.w, ch, config)?;
                 end += ch.len_utf8();
                 beg = end;
             }
         }
 
         if end - beg > 0 {
-            self.w.write_all(val[beg..end].as_bytes())?;
+            self.w.write_all(&val.as_bytes()[beg..end])?;
         }
 
         self.w.write_all(b"\"")?;
@@ -271,8 +510,174 @@ where
         Ok(())
     }
 
+    // Enters the name space holding the content of an enum variant, writing
+    // its tag first if needed. Returns true if a name space was entered.
+    fn enter_variant(&mut self, variant: &'static str) -> Result<bool> {
+        let config = Arc::clone(&self.config);
+
+        match &config.tagging {
+            TagStyle::Untagged => Ok(false),
+            TagStyle::External => {
+                self.enter_ns(&variant);
+                Ok(true)
+            }
+            TagStyle::Adjacent { tag, content } => {
+                self.enter_ns(tag);
+                let res = ser::Serializer::serialize_str(&mut *self, variant);
+                self.leave_ns();
+                res?;
+
+                self.enter_ns(content);
+                Ok(true)
+            }
+        }
+    }
+
+    // Serializes a value under the current key, writing bools in the given style
+    // and nulls out even in styles that would leave the key out.
+    fn serialize_explicit<T>(&mut self, value: &T, bools: BoolStyle) -> Result<()>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        let mode = Mode {
+            explicit: Some((self.ns.len(), bools)),
+            ..self.mode
+        };
+
+        self.with_mode(mode, |s| s.serialize_child(value))
+    }
+
+    // Serializes a value in the given mode, restoring the current one after.
+    #[inline]
+    fn with_mode<F>(&mut self, mode: Mode, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut Self) -> Result<()>,
+    {
+        let outer = mem::replace(&mut self.mode, mode);
+        let res = f(self);
+        self.mode = outer;
+        res
+    }
+
+    // Returns true if a scalar is being written as an element of a compact
+    // sequence.
+    #[inline]
+    fn in_element(&self) -> bool {
+        !self.written.nested && self.mode.element == Some(self.ns.len())
+    }
+
+    // Returns true if a bool or null is written under the key passed to
+    // `serialize_explicit`, rather than under a key nested beneath it.
+    #[inline]
+    fn is_explicit(&self) -> bool {
+        self.mode
+            .explicit
+            .is_some_and(|(depth, _)| depth == self.ns.len())
+    }
+
+    // Writes a float in the configured style. The caller says whether the
+    // float is finite since there's no trait for asking.
+    fn write_float<F>(&mut self, v: F, finite: bool) -> Result<()>
+    where
+        F: dtoa::Float + Display + LowerExp + FromStr + Into<f64>,
+    {
+        let (style, non_finite) = (self.config.floats, self.config.non_finite);
+
+        if !finite {
+            match non_finite {
+                NonFiniteStyle::Token => {}
+                NonFiniteStyle::Null => return self.write_null(&NullStyle::Null),
+                // like an omitted `None`, a variant's payload is still written
+                NonFiniteStyle::Omit if self.is_explicit() => {
+                    return self.write_null(&NullStyle::Null)
+                }
+                NonFiniteStyle::Omit => return Ok(()),
+                NonFiniteStyle::Error => {
+                    return Err(ser::Error::custom(format_args!(
+                        "cannot write {} at `{}`",
+                        dtoa::Buffer::new().format(v),
+                        self.path()
+                    )))
+                }
+            }
+        }
+
+        if self.write_key()? {
+            self.w.write_all(b"=")?;
+        }
+
+        match style {
+            FloatStyle::Fixed(places) if finite => write!(self.w, "{:.*}", places, v)?,
+            FloatStyle::Significant(digits) if finite => {
+                // round by going through scientific notation
+                let mut buf = [0u8; 32];
+                let mut cur = &mut buf[..];
+                write!(cur, "{:.*e}", digits - 1, v)?;
+                let len = 32 - cur.len();
+
+                let rounded = std::str::from_utf8(&buf[..len])
+                    .ok()
+                    .and_then(|s| s.parse::<F>().ok())
+                    .expect("floats parse from their own scientific notation");
+                match rounded.into().is_finite() {
+                    true => Self::write_plain_float(&mut self.w, rounded)?,
+                    false => Self::write_plain_float(&mut self.w, v)?,
+                }
+            }
+            FloatStyle::Plain if finite => Self::write_plain_float(&mut self.w, v)?,
+            _ => {
+                let mut buf = dtoa::Buffer::new();
+                self.w.write_all(buf.format(v).as_bytes())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Writes a finite float without an exponent, keeping a fractional part
+    // so that it still reads as a float.
+    fn write_plain_float<F: Display>(w: &mut B, v: F) -> Result<()> {
+        // long enough for every digit of the largest and smallest f64
+        let mut buf = [0u8; 400];
+        let mut cur = &mut buf[..];
+        write!(cur, "{}", v)?;
+        let len = 400 - cur.len();
+
+        w.write_all(&buf[..len])?;
+        if !buf[..len].contains(&b'.') {
+            w.write_all(b".0")?;
+        }
+
+        Ok(())
+    }
+
+    // Writes a None or unit value in the given style.
+    fn write_null(&mut self, style: &NullStyle) -> Result<()> {
+        if *style == NullStyle::Omit {
+            return Ok(());
+        }
+
+        if self.write_key()? {
+            self.w.write_all(b"=")?;
+        }
+
+        match style {
+            NullStyle::Null => self.w.write_all(b"null")?,
+            NullStyle::Token(token) => self.write_val(token)?,
+            NullStyle::Omit | NullStyle::Empty => {}
+        }
+
+        Ok(())
+    }
+
     // Returns true if a key was written
     fn write_key(&mut self) -> Result<bool> {
+        self.written.pairs += 1;
+
+        if self.in_element() {
+            return Ok(false);
+        }
+
         if self.have_written {
             self.w.write_all(b" ")?;
         } else {
@@ -283,16 +688,147 @@ where
             return Ok(false);
         }
 
-        for (idx, ns) in self.ns.iter().enumerate() {
-            Self::write_ident(&mut self.w, ns)?;
+        self.written.nested = true;
+        let config = &*self.config;
+        let mut buf: [u8; 4] = [0; 4];
+        let separator = config.separator.encode_utf8(&mut buf).as_bytes();
+
+        for (idx, (part, index)) in key_parts(&self.ns).enumerate() {
+            if index && config.indices == IndexStyle::Brackets {
+                self.w.write_all(b"[")?;
+                self.w.write_all(part.as_bytes())?;
+                self.w.write_all(b"]")?;
+                continue;
+            }
 
-            if idx + 1 < self.ns.len() {
-                self.w.write_all(b".")?;
+            if idx > 0 {
+                self.w.write_all(separator)?;
             }
+
+            Self::write_ident(&mut self.w, config, &part).map_err(|e| match e {
+                // report the whole path rather than the segment
+                Error::InvalidIdentifier { .. } => Error::InvalidIdentifier { key: self.path() },
+                Error::EmptyIdentifier { .. } => Error::EmptyIdentifier { key: self.path() },
+                e => e,
+            })?;
         }
 
         Ok(true)
     }
+
+    // Remembers how many pairs were written before a struct or map, if empty
+    // ones are written at all.
+    #[inline]
+    fn start_container(&mut self) {
+        self.written.nested = true;
+        if self.config.empty_containers != EmptyContainerStyle::Omit {
+            self.starts.push(self.written.pairs);
+        }
+    }
+
+    // Writes the placeholder for an empty struct or map started with
+    // `start_container`.
+    #[inline]
+    fn end_container(&mut self) -> Result<()> {
+        match self.starts.pop() {
+            Some(start) => self.write_empty_container(start, "{}"),
+            None => Ok(()),
+        }
+    }
+
+    // Writes the placeholder for a struct, map or sequence if no pairs were
+    // written since `start`.
+    fn write_empty_container(&mut self, start: usize, brackets: &str) -> Result<()> {
+        if self.written.pairs != start || self.ns.is_empty() {
+            return Ok(());
+        }
+
+        let val = match self.config.empty_containers {
+            EmptyContainerStyle::Omit => return Ok(()),
+            EmptyContainerStyle::Brackets => brackets,
+            EmptyContainerStyle::Empty => "",
+        };
+
+        self.write_key()?;
+        self.w.write_all(b"=")?;
+        self.write_val(val)
+    }
+
+    // Serializes a value beneath the key just entered, embedding it as JSON if
+    // it is nested and the configuration asks for it. The JSON is left to
+    // serde_json entirely, so none of the configuration applies to it.
+    fn serialize_child<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + serde::Serialize,
+    {
+        #[cfg(feature = "json")]
+        if self.embeds_json() {
+            let json = serde_json::to_string(value).map_err(<Error as ser::Error>::custom)?;
+
+            if json.starts_with(['{', '[']) {
+                self.written.nested = true;
+                if self.write_key()? {
+                    self.w.write_all(b"=")?;
+                }
+
+                // the JSON has to read back exactly as it was written
+                let config = self.json_config();
+                let outer = mem::replace(&mut self.config, config);
+                let res = self.write_val(&json);
+                self.config = outer;
+                return res;
+            }
+        }
+
+        value.serialize(self)
+    }
+
+    // Returns the configuration embedded JSON is written with, which escapes it
+    // losslessly.
+    #[cfg(feature = "json")]
+    fn json_config(&mut self) -> Arc<SerializerConfig> {
+        let config = &self.config;
+        let json_config = self.json_config.get_or_insert_with(|| {
+            Arc::new(SerializerConfig {
+                escapes: EscapeStyle::Lossless,
+                ..SerializerConfig::clone(config)
+            })
+        });
+
+        Arc::clone(json_config)
+    }
+
+    // Returns true if nested values beneath the current key are embedded as JSON.
+    #[cfg(feature = "json")]
+    fn embeds_json(&self) -> bool {
+        let config = &*self.config;
+
+        config
+            .json_depth
+            .is_some_and(|depth| self.ns.len() >= depth)
+            || (!config.json_keys.is_empty() && config.json_keys.contains(&self.path()))
+    }
+
+    // Returns the key being written, unescaped, for error messages.
+    fn path(&self) -> String {
+        let mut path = String::new();
+
+        for (idx, (part, index)) in key_parts(&self.ns).enumerate() {
+            if index && self.config.indices == IndexStyle::Brackets {
+                path.push('[');
+                path.push_str(&part);
+                path.push(']');
+                continue;
+            }
+
+            if idx > 0 {
+                path.push(self.config.separator);
+            }
+            path.push_str(&part);
+        }
+
+        path
+    }
 }
 
 impl<'a, B> ser::Serializer for &'a mut Serializer<B>
@@ -308,12 +844,29 @@ where
     type SerializeTuple = LogfmtSeqSerializer<'a, B>;
     type SerializeTupleStruct = LogfmtSeqSerializer<'a, B>;
     type SerializeTupleVariant = LogfmtSeqSerializer<'a, B>;
-    type SerializeStructVariant = Self;
+    type SerializeStructVariant = LogfmtStructVariantSerializer<'a, B>;
 
     #[inline]
     fn serialize_bool(self, v: bool) -> Result<Self::Ok> {
-        if v {
-            self.write_key()?;
+        self.written.last_bool = Some(v);
+
+        let style = match self.mode.explicit {
+            Some((depth, style)) if depth == self.ns.len() => style,
+            _ => self.config.bools,
+        };
+
+        match (v, style) {
+            (true, BoolStyle::Bare | BoolStyle::BareTrue) => {
+                self.write_key()?;
+            }
+            (false, BoolStyle::Bare) => {}
+            _ => {
+                if self.write_key()? {
+                    self.w.write_all(b"=")?;
+                }
+
+                self.w.write_all(if v { b"true" } else { b"false" })?;
+            }
         }
 
         Ok(())
@@ -321,24 +874,12 @@ where
 
     #[inline]
     fn serialize_f32(self, v: f32) -> Result<Self::Ok> {
-        if self.write_key()? {
-            self.w.write_all(b"=")?;
-        }
-
-        let mut buf = dtoa::Buffer::new();
-        self.w.write_all(buf.format(v).as_bytes())?;
-        Ok(())
+        self.write_float(v, v.is_finite())
     }
 
     #[inline]
     fn serialize_f64(self, v: f64) -> Result<Self::Ok> {
-        if self.write_key()? {
-            self.w.write_all(b"=")?;
-        }
-
-        let mut buf = dtoa::Buffer::new();
-        self.w.write_all(buf.format(v).as_bytes())?;
-        Ok(())
+        self.write_float(v, v.is_finite())
     }
 
     #[inline]
@@ -456,45 +997,78 @@ where
 
     #[inline]
     fn serialize_str(self, v: &str) -> Result<Self::Ok> {
+        self.written.quoted = self.mode.quoted || (v.is_empty() && self.config.quote_empty);
+
         if self.write_key()? {
             self.w.write_all(b"=")?;
+
+            if v.is_empty() && self.config.quote_empty {
+                self.w.write_all(b"\"\"")?;
+                return Ok(());
+            }
         }
 
         self.write_val(v)
     }
 
-    #[inline]
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok> {
-        let encoded = base16::encode_upper(v);
-        self.serialize_str(&encoded)
-    }
+        let style = self.config.bytes;
+
+        if style == BytesStyle::Utf8 {
+            if let Ok(s) = std::str::from_utf8(v) {
+                return self.serialize_str(s);
+            }
+        }
 
-    #[inline]
-    fn serialize_none(self) -> Result<Self::Ok> {
         if self.write_key()? {
             self.w.write_all(b"=")?;
         }
 
-        self.w.write_all(b"null")?;
+        match style {
+            BytesStyle::UpperHex | BytesStyle::Utf8 => write_hex(&mut self.w, v, HEX_UPPER)?,
+            BytesStyle::LowerHex => write_hex(&mut self.w, v, HEX_LOWER)?,
+            BytesStyle::Base64 => {
+                // padding needs quotes
+                let quoted = v.len() % 3 != 0 && !self.mode.raw && !self.in_element();
+
+                if quoted {
+                    self.w.write_all(b"\"")?;
+                }
+                write_base64(&mut self.w, v, BASE64, true)?;
+                if quoted {
+                    self.w.write_all(b"\"")?;
+                }
+            }
+            BytesStyle::Base64Url => write_base64(&mut self.w, v, BASE64_URL, false)?,
+        }
+
         Ok(())
     }
 
     #[inline]
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok>
+    fn serialize_none(self) -> Result<Self::Ok> {
+        let config = Arc::clone(&self.config);
+        match (&config.none, self.is_explicit()) {
+            (NullStyle::Omit, true) => self.write_null(&NullStyle::Null),
+            (style, _) => self.write_null(style),
+        }
+    }
+
+    #[inline]
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
         value.serialize(self)
     }
 
-    // Treat the same as an empty string (i.e. nothing)
     #[inline]
     fn serialize_unit(self) -> Result<Self::Ok> {
-        if self.write_key()? {
-            self.w.write_all(b"=")?;
+        let config = Arc::clone(&self.config);
+        match (&config.unit, self.is_explicit()) {
+            (NullStyle::Omit, true) => self.write_null(&NullStyle::Empty),
+            (style, _) => self.write_null(style),
         }
-
-        Ok(())
     }
 
     #[inline]
@@ -502,54 +1076,102 @@ where
         self.serialize_unit()
     }
 
-    // Write the name of the enum::variant
-
+    // Write the name of the variant as configured, enum::variant by default
     #[inline]
     fn serialize_unit_variant(
         self,
         name: &'static str,
-        _variant_index: u32,
+        variant_index: u32,
         variant: &'static str,
     ) -> Result<Self::Ok> {
-        let mut s = String::with_capacity(name.len() + variant.len() + 2);
-        s.push_str(name);
-        s.push_str("::");
-        s.push_str(variant);
-        self.serialize_str(&s)?;
-
-        Ok(())
+        match self.config.variants {
+            VariantStyle::Qualified => {
+                let mut s = String::with_capacity(name.len() + variant.len() + 2);
+                s.push_str(name);
+                s.push_str("::");
+                s.push_str(variant);
+                self.serialize_str(&s)
+            }
+            VariantStyle::Name => self.serialize_str(variant),
+            VariantStyle::Lowercase => self.serialize_str(&variant.to_lowercase()),
+            VariantStyle::SnakeCase => self.serialize_str(&to_snake_case(variant)),
+            VariantStyle::Index => self.serialize_u32(variant_index),
+        }
     }
 
     #[inline]
-    fn serialize_newtype_struct<T: ?Sized>(self, _name: &'static str, value: &T) -> Result<Self::Ok>
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
+        // Value::Bare is a bare key whatever the bool style
+        if name == BARE_TOKEN {
+            self.write_key()?;
+            return Ok(());
+        }
+
+        // Value::Bool is written out whatever the bool style, so that it reads
+        // back as a bool rather than a bare key or a missing one
+        if name == BOOL_TOKEN {
+            return self.serialize_explicit(value, BoolStyle::Explicit);
+        }
+
+        // Value::String is quoted whatever it holds, so that it reads back as a
+        // string rather than a number, a bool or null
+        if name == STRING_TOKEN {
+            let mode = Mode {
+                quoted: true,
+                ..self.mode
+            };
+            return self.with_mode(mode, |s| value.serialize(s));
+        }
+
+        // the keys of a Record are whole paths rather than single segments
+        if name == RECORD_TOKEN {
+            let mode = Mode {
+                record_keys: true,
+                ..self.mode
+            };
+            return self.with_mode(mode, |s| value.serialize(s));
+        }
+
         value.serialize(self)
     }
 
     #[inline]
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        value.serialize(self)
+        // A payload of `false` or an omitted `None` would leave the variant
+        // without content, or out entirely
+        let nested = self.enter_variant(variant)?;
+        let bools = match self.config.bools {
+            BoolStyle::Bare => BoolStyle::BareTrue,
+            style => style,
+        };
+        let res = self.serialize_explicit(value, bools);
+
+        if nested {
+            self.leave_ns();
+        }
+        res
     }
 
     #[inline]
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
-        Ok(LogfmtSeqSerializer { s: self, idx: 0 })
+        Ok(LogfmtSeqSerializer::new(self, false))
     }
 
     #[inline]
     fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
-        Ok(LogfmtSeqSerializer { s: self, idx: 0 })
+        Ok(LogfmtSeqSerializer::new(self, false))
     }
 
     #[inline]
@@ -558,7 +1180,7 @@ where
         _name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        Ok(LogfmtSeqSerializer { s: self, idx: 0 })
+        Ok(LogfmtSeqSerializer::new(self, false))
     }
 
     #[inline]
@@ -566,19 +1188,23 @@ where
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        Ok(LogfmtSeqSerializer { s: self, idx: 0 })
+        let nested = self.enter_variant(variant)?;
+
+        Ok(LogfmtSeqSerializer::new(self, nested))
     }
 
     #[inline]
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        self.start_container();
         Ok(self)
     }
 
     #[inline]
     fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        self.start_container();
         Ok(self)
     }
 
@@ -587,27 +1213,37 @@ where
         self,
         _name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        Ok(self)
+        let nested = self.enter_variant(variant)?;
+        self.written.nested = true;
+
+        let start = self.written.pairs;
+        Ok(LogfmtStructVariantSerializer {
+            s: self,
+            nested,
+            start,
+        })
     }
 }
 
-impl<'a, B> SerializeStruct for &'a mut Serializer<B>
+impl<B> SerializeStruct for &mut Serializer<B>
 where
     B: Write,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        self.enter_ns(&key);
+        if !self.enter_key(key) {
+            return Ok(());
+        }
 
-        if let Err(e) = value.serialize(&mut **self) {
+        if let Err(e) = self.serialize_child(value) {
             self.leave_ns();
             return Err(e);
         }
@@ -623,30 +1259,30 @@ where
 
     #[inline]
     fn end(self) -> Result<Self::Ok> {
-        Ok(())
+        self.end_container()
     }
 }
 
-impl<'a, B> SerializeStructVariant for &'a mut Serializer<B>
+#[doc(hidden)]
+/// Type to help serialize struct variants.
+pub struct LogfmtStructVariantSerializer<'a, B> {
+    s: &'a mut Serializer<B>,
+    nested: bool,
+    start: usize,
+}
+
+impl<B> SerializeStructVariant for LogfmtStructVariantSerializer<'_, B>
 where
     B: Write,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        self.enter_ns(&key);
-
-        if let Err(e) = value.serialize(&mut **self) {
-            self.leave_ns();
-            return Err(e);
-        }
-
-        self.leave_ns();
-        Ok(())
+        SerializeStruct::serialize_field(&mut self.s, key, value)
     }
 
     #[inline]
@@ -656,39 +1292,47 @@ where
 
     #[inline]
     fn end(self) -> Result<Self::Ok> {
+        self.s.write_empty_container(self.start, "{}")?;
+
+        if self.nested {
+            self.s.leave_ns();
+        }
+
         Ok(())
     }
 }
 
-impl<'a, B> SerializeMap for &'a mut Serializer<B>
+impl<B> SerializeMap for &mut Serializer<B>
 where
     B: Write,
 {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<()>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        let mut key_as_logfmt = Serializer {
-            w: Vec::with_capacity(64),
-            ns: Vec::with_capacity(8),
-            have_written: false,
-        };
+        let mut key_as_logfmt =
+            Serializer::with_config(Vec::with_capacity(64), Arc::clone(&self.config));
+        key_as_logfmt.mode.raw = true;
 
         key.serialize(&mut key_as_logfmt)?;
         let k = unsafe { String::from_utf8_unchecked(key_as_logfmt.w) };
-        self.enter_ns(&k);
+        self.skip_value = !self.enter_key(&k);
 
         Ok(())
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        if let Err(e) = value.serialize(&mut **self) {
+        if mem::take(&mut self.skip_value) {
+            return Ok(());
+        }
+
+        if let Err(e) = self.serialize_child(value) {
             self.leave_ns();
             return Err(e);
         }
@@ -696,10 +1340,10 @@ where
         Ok(())
     }
 
-    fn serialize_entry<K: ?Sized, V: ?Sized>(&mut self, key: &K, value: &V) -> Result<()>
+    fn serialize_entry<K, V>(&mut self, key: &K, value: &V) -> Result<()>
     where
-        K: serde::Serialize,
-        V: serde::Serialize,
+        K: ?Sized + serde::Serialize,
+        V: ?Sized + serde::Serialize,
     {
         self.serialize_key(key)?;
         self.serialize_value(value)?;
@@ -708,7 +1352,7 @@ where
 
     #[inline]
     fn end(self) -> Result<Self::Ok> {
-        Ok(())
+        self.end_container()
     }
 }
 
@@ -717,6 +1361,23 @@ where
 pub struct LogfmtSeqSerializer<'a, B> {
     s: &'a mut Serializer<B>,
     idx: usize,
+    nested: bool,
+    start: usize,
+    // The elements written so far, while the sequence can still be compact.
+    compact: Option<Vec<Element>>,
+}
+
+// An element of a sequence written while the sequence can still be compact.
+enum Element {
+    // Nothing was written, e.g. for an omitted `None`.
+    Omitted,
+    // Kept apart so that it's written in the configured style if the sequence
+    // ends up exploded.
+    Bool(bool),
+    // Written as it would be on its own, but unescaped.
+    Scalar(String),
+    // A string that is quoted if the sequence ends up exploded.
+    Quoted(String),
 }
 
 impl<'a, B> LogfmtSeqSerializer<'a, B>
@@ -724,14 +1385,98 @@ where
     B: Write,
 {
     #[inline]
-    fn serialize_element_internal<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn new(s: &'a mut Serializer<B>, nested: bool) -> Self {
+        s.written.nested = true;
+        let compact = match s.config.seqs {
+            SeqStyle::Exploded => None,
+            SeqStyle::Joined(_) | SeqStyle::Bracketed => Some(Vec::new()),
+        };
+
+        LogfmtSeqSerializer {
+            start: s.written.pairs,
+            s,
+            idx: 0,
+            nested,
+            compact,
+        }
+    }
+
+    fn serialize_element_internal<T>(&mut self, value: &T) -> Result<()>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
-        let mut buf = itoa::Buffer::new();
-        self.s.enter_ns(&buf.format(self.idx));
+        if let Some(elements) = &mut self.compact {
+            // each element is written once, beneath the sequence's key, and
+            // what it wrote is looked at on its own
+            let mut child = Serializer::with_config(Vec::new(), Arc::clone(&self.s.config));
+            child.ns = mem::take(&mut self.s.ns);
+            child.enter_index(self.idx);
+            child.mode = Mode {
+                element: Some(child.ns.len()),
+                ..self.s.mode
+            };
+            let res = child.serialize_child(value);
+            child.leave_ns();
+            self.s.ns = mem::take(&mut child.ns);
+            res?;
+
+            if !child.written.nested {
+                let val = unsafe { String::from_utf8_unchecked(child.w) };
+                elements.push(match child.written.last_bool {
+                    Some(v) => Element::Bool(v),
+                    None if child.written.pairs == 0 => Element::Omitted,
+                    None if child.written.quoted => Element::Quoted(val),
+                    None => Element::Scalar(val),
+                });
+                self.idx += 1;
+                return Ok(());
+            }
+
+            // a nested element, so write what came before as pairs of their own
+            for (idx, element) in mem::take(elements).into_iter().enumerate() {
+                self.s.enter_index(idx);
+                let res = match element {
+                    Element::Omitted => Ok(()),
+                    Element::Bool(v) => ser::Serializer::serialize_bool(&mut *self.s, v),
+                    Element::Scalar(val) => self.s.write_key().and_then(|keyed| {
+                        if keyed {
+                            self.s.w.write_all(b"=")?;
+                        }
+                        self.s.write_val(&val)
+                    }),
+                    Element::Quoted(val) => self.s.write_key().and_then(|keyed| {
+                        if keyed {
+                            self.s.w.write_all(b"=")?;
+                        }
+                        let mode = Mode {
+                            quoted: true,
+                            ..self.s.mode
+                        };
+                        self.s.with_mode(mode, |s| s.write_val(&val))
+                    }),
+                };
+                self.s.leave_ns();
+                res?;
+            }
+
+            self.compact = None;
+
+            // then the pairs the nested element wrote
+            if !child.w.is_empty() {
+                if self.s.have_written {
+                    self.s.w.write_all(b" ")?;
+                }
+                self.s.w.write_all(&child.w)?;
+                self.s.have_written = true;
+            }
+            self.s.written.pairs += child.written.pairs;
+            self.idx += 1;
+            return Ok(());
+        }
+
+        self.s.enter_index(self.idx);
 
-        if let Err(e) = value.serialize(&mut *self.s) {
+        if let Err(e) = self.s.serialize_child(value) {
             self.s.leave_ns();
             return Err(e);
         }
@@ -742,6 +1487,65 @@ where
 
         Ok(())
     }
+
+    // Writes the elements of a compact sequence as a single value, escaped as
+    // a whole. Strings that have to stay quoted and elements holding the
+    // delimiter couldn't be told apart from others once joined, so they fail.
+    fn end_internal(self) -> Result<()> {
+        if let Some(elements) = self.compact {
+            let delim = match self.s.config.seqs {
+                SeqStyle::Joined(delim) => delim,
+                _ => ',',
+            };
+
+            let mut joined = Vec::with_capacity(elements.len());
+            for element in &elements {
+                joined.push(match element {
+                    Element::Omitted => continue,
+                    Element::Bool(v) => {
+                        if *v {
+                            "true"
+                        } else {
+                            "false"
+                        }
+                    }
+                    Element::Scalar(val) if !val.contains(delim) => val.as_str(),
+                    Element::Scalar(val) | Element::Quoted(val) => {
+                        return Err(ser::Error::custom(format_args!(
+                            "cannot write `{}` in the compact sequence at `{}`",
+                            val,
+                            self.s.path()
+                        )))
+                    }
+                });
+            }
+            let elements = joined;
+
+            let val = match self.s.config.seqs {
+                SeqStyle::Joined(_) if elements.is_empty() => None,
+                SeqStyle::Joined(delim) => {
+                    let mut buf: [u8; 4] = [0; 4];
+                    Some(elements.join(&*delim.encode_utf8(&mut buf)))
+                }
+                _ => Some(format!("[{}]", elements.join(","))),
+            };
+
+            if let Some(val) = val {
+                if self.s.write_key()? {
+                    self.s.w.write_all(b"=")?;
+                }
+                self.s.write_val(&val)?;
+            }
+        }
+
+        self.s.write_empty_container(self.start, "[]")?;
+
+        if self.nested {
+            self.s.leave_ns();
+        }
+
+        Ok(())
+    }
 }
 
 impl<'a, B> SerializeSeq for LogfmtSeqSerializer<'a, B>
@@ -751,16 +1555,16 @@ where
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
         self.serialize_element_internal(value)
     }
 
     #[inline]
     fn end(self) -> Result<Self::Ok> {
-        Ok(())
+        self.end_internal()
     }
 }
 
@@ -771,16 +1575,16 @@ where
     type Ok = ();
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
         self.serialize_element_internal(value)
     }
 
     #[inline]
     fn end(self) -> Result<Self::Ok> {
-        Ok(())
+        self.end_internal()
     }
 }
 
@@ -791,16 +1595,16 @@ where
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
         self.serialize_element_internal(value)
     }
 
     #[inline]
     fn end(self) -> Result<Self::Ok> {
-        Ok(())
+        self.end_internal()
     }
 }
 
@@ -811,29 +1615,36 @@ where
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
-        T: serde::Serialize,
+        T: ?Sized + serde::Serialize,
     {
         self.serialize_element_internal(value)
     }
 
     #[inline]
     fn end(self) -> Result<Self::Ok> {
-        Ok(())
+        self.end_internal()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Result, Serializer};
+    use super::{
+        BoolStyle, BytesStyle, ControlStyle, EmptyContainerStyle, EmptyKeyStyle, Error,
+        EscapeStyle, FloatStyle, KeyStyle, NonFiniteStyle, NullStyle, Result, SeqStyle, Serializer,
+        SerializerBuilder, SerializerConfig, TagStyle, VariantStyle,
+    };
+    use crate::{Deserializer, Record, Value};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_write_ident() {
         fn try_ident(s: &str) -> Result<String> {
             let mut v = Vec::new();
 
-            Serializer::write_ident(&mut v, s)?;
+            Serializer::write_ident(&mut v, &SerializerConfig::default(), s)?;
 
             Ok(unsafe { String::from_utf8_unchecked(v) })
         }
@@ -845,7 +1656,298 @@ mod tests {
         assert_eq!(try_ident("spaceattheend ").unwrap(), "spaceattheend%20");
         assert_eq!(try_ident("=equalsbeg").unwrap(), "%3Dequalsbeg");
         assert_eq!(try_ident("!\0").unwrap(), "!%00");
-        assert!(try_ident("").is_err())
+        assert_eq!(try_ident("100%").unwrap(), "100%25");
+        assert!(try_ident("").is_err());
+
+        let mut config = SerializerConfig::default();
+        let mut try_keys = |keys: KeyStyle, s: &str| -> Result<String> {
+            let mut v = Vec::new();
+            config.keys = keys;
+
+            Serializer::write_ident(&mut v, &config, s)?;
+
+            Ok(unsafe { String::from_utf8_unchecked(v) })
+        };
+
+        assert_eq!(
+            try_keys(KeyStyle::Replace('_'), "has spaces").unwrap(),
+            "has_spaces"
+        );
+        assert_eq!(try_keys(KeyStyle::Replace('_'), "=a b\"").unwrap(), "_a_b_");
+        assert_eq!(try_keys(KeyStyle::Replace('日'), "a=b").unwrap(), "a日b");
+        assert_eq!(try_keys(KeyStyle::Replace(' '), "a=b").unwrap(), "a%20b");
+        assert_eq!(try_keys(KeyStyle::Replace('_'), "100%").unwrap(), "100%");
+        assert_eq!(
+            try_keys(KeyStyle::Strip, " has = spaces ").unwrap(),
+            "hasspaces"
+        );
+        assert!(matches!(
+            try_keys(KeyStyle::Strip, " = "),
+            Err(Error::EmptyIdentifier { .. })
+        ));
+        assert_eq!(try_keys(KeyStyle::Error, "valid_key").unwrap(), "valid_key");
+        assert!(matches!(
+            try_keys(KeyStyle::Error, "has spaces"),
+            Err(Error::InvalidIdentifier { key }) if key == "has spaces"
+        ));
+    }
+
+    #[test]
+    fn test_percent_keys() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Doc {
+            m: BTreeMap<String, u8>,
+        }
+
+        let doc = Doc {
+            m: [("a.b", 1), ("a%2Eb", 2), ("a%20b", 3), ("\"x\"", 4)]
+                .into_iter()
+                .map(|(k, v)| (k.to_owned(), v))
+                .collect(),
+        };
+
+        let s = crate::to_string(&doc).unwrap();
+        assert_eq!(s, "m.%22x%22=4 m.a%2520b=3 m.a%252Eb=2 m.a%2Eb=1");
+        assert_eq!(crate::from_str::<Doc>(&s).unwrap(), doc);
+    }
+
+    #[test]
+    fn test_invalid_key_path() {
+        #[derive(Serialize)]
+        struct Outer {
+            ok: u8,
+            inner: BTreeMap<&'static str, u8>,
+        }
+
+        let outer = Outer {
+            ok: 1,
+            inner: [("a b", 2)].into_iter().collect(),
+        };
+
+        let mut ser = Serializer::builder()
+            .with_keys(KeyStyle::Error)
+            .build(Vec::new());
+        let err = outer.serialize(&mut ser).unwrap_err();
+        assert!(matches!(&err, Error::InvalidIdentifier { key } if key == "inner.a b"));
+        assert_eq!(
+            err.to_string(),
+            "cannot write the key `inner.a b` as it contains invalid characters"
+        );
+
+        let mut ser = Serializer::builder()
+            .with_keys(KeyStyle::Replace('_'))
+            .build(Vec::new());
+        outer.serialize(&mut ser).unwrap();
+        assert_eq!(ser.writer(), b"ok=1 inner.a_b=2");
+    }
+
+    #[test]
+    fn test_lossless() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Msg {
+            msg: String,
+        }
+
+        let mut ser = Serializer::builder()
+            .with_escapes(EscapeStyle::Lossless)
+            .build(Vec::new());
+
+        let inputs = [
+            "plain",
+            "a literal \\n and a newline \n",
+            "\\\"quoted\\\"",
+            "trailing \\",
+            "\\x41 \\u{41} \\u0041",
+            "\x01\x1B[31m\x7F ␡ \0\t\r",
+            "no\\space",
+            "",
+        ];
+
+        for s in inputs {
+            let msg = Msg { msg: s.to_owned() };
+            msg.serialize(&mut ser).unwrap();
+            ser.next().unwrap();
+        }
+
+        let out = String::from_utf8(ser.writer()).unwrap();
+        assert_eq!(
+            out,
+            "msg=plain\n\
+            msg=\"a literal \\\\n and a newline \\n\"\n\
+            msg=\"\\\\\\\"quoted\\\\\\\"\"\n\
+            msg=\"trailing \\\\\"\n\
+            msg=\"\\\\x41 \\\\u{41} \\\\u0041\"\n\
+            msg=\"\\x01\\x1b[31m\\x7f ␡ \\0\\t\\r\"\n\
+            msg=no\\space\n\
+            msg=\n"
+        );
+
+        let read: Vec<String> = out
+            .lines()
+            .map(|line| crate::from_str::<Msg>(line).unwrap().msg)
+            .collect();
+        assert_eq!(read, inputs);
+    }
+
+    #[test]
+    fn test_controls() {
+        let s = "\0 \x07\x08\x0B\x0C \x1B[0m \x7F \u{85} \u{A0} \u{2028} \\n";
+
+        for (controls, expected) in [
+            (
+                ControlStyle::Pictures,
+                "\"\\0 ␇␈␋␌ ␛[0m ␡ \u{85} \u{A0} \u{2028} \\n\"",
+            ),
+            (
+                ControlStyle::Hex,
+                "\"\\0 \\x07\\x08\\x0b\\x0c \\x1b[0m \\x7f \\x85 \u{A0} \u{2028} \\n\"",
+            ),
+            (
+                ControlStyle::Unicode,
+                "\"\\u0000 \\u0007\\u0008\\u000b\\u000c \\u001b[0m \\u007f \\u0085 \u{A0} \u{2028} \\n\"",
+            ),
+            (
+                ControlStyle::Go,
+                "\"\\x00 \\a\\b\\v\\f \\x1b[0m \\x7f \\u0085 \\u00a0 \\u2028 \\n\"",
+            ),
+        ] {
+            let mut ser = Serializer::builder()
+                .with_controls(controls)
+                .build(Vec::new());
+            ser.write_val(s).unwrap();
+
+            let out = String::from_utf8(ser.writer()).unwrap();
+            assert_eq!(out, expected);
+
+            // everything but control pictures decodes back
+            if controls != ControlStyle::Pictures {
+                let read: String = crate::from_str(&out).unwrap();
+                assert_eq!(read, s.replace("\\n", "\n"));
+            }
+        }
+
+        // characters that need escaping force quotes
+        let mut ser = Serializer::builder()
+            .with_controls(ControlStyle::Go)
+            .build(Vec::new());
+        ser.write_val("a\u{A0}b").unwrap();
+        assert_eq!(ser.writer(), b"\"a\\u00a0b\"");
+
+        // as are format characters, matching Go's strconv.Quote
+        for (s, expected) in [
+            ("a\u{200B}b c", "\"a\\u200bb c\""),
+            ("\u{AD}x\u{FEFF}", "\"\\u00adx\\ufeff\""),
+            ("\u{202E}abc\u{2066}", "\"\\u202eabc\\u2066\""),
+            ("tag\u{E0001}", "\"tag\\U000e0001\""),
+            ("\u{3164}", "\u{3164}"),
+        ] {
+            let mut ser = Serializer::builder()
+                .with_controls(ControlStyle::Go)
+                .build(Vec::new());
+            ser.write_val(s).unwrap();
+
+            let out = String::from_utf8(ser.writer()).unwrap();
+            assert_eq!(out, expected);
+            assert_eq!(crate::from_str::<String>(&out).unwrap(), s);
+        }
+    }
+
+    #[test]
+    fn test_ascii_only() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Msg {
+            #[serde(rename = "ключ")]
+            key: String,
+            map: BTreeMap<String, u8>,
+        }
+
+        let msg = Msg {
+            key: "héllo wörld 😀\x01\u{85}".to_owned(),
+            map: [("日本".to_owned(), 1)].into_iter().collect(),
+        };
+
+        for (controls, expected) in [
+            (
+                ControlStyle::Pictures,
+                "%D0%BA%D0%BB%D1%8E%D1%87=\"h\\u00e9llo w\\u00f6rld \\u{1f600}\\x01\\x85\" \
+                map.%E6%97%A5%E6%9C%AC=1",
+            ),
+            (
+                ControlStyle::Go,
+                "%D0%BA%D0%BB%D1%8E%D1%87=\"h\\u00e9llo w\\u00f6rld \\U0001f600\\x01\\u0085\" \
+                map.%E6%97%A5%E6%9C%AC=1",
+            ),
+        ] {
+            let mut ser = Serializer::builder()
+                .with_ascii_only(true)
+                .with_controls(controls)
+                .build(Vec::new());
+            msg.serialize(&mut ser).unwrap();
+
+            let out = String::from_utf8(ser.writer()).unwrap();
+            assert!(out.is_ascii());
+            assert_eq!(out, expected);
+
+            assert_eq!(crate::from_str::<Msg>(&out).unwrap(), msg);
+        }
+
+        // unquoted values that would need escaping get quotes
+        let mut ser = Serializer::builder()
+            .with_ascii_only(true)
+            .build(Vec::new());
+        ser.write_val("café").unwrap();
+        assert_eq!(ser.writer(), b"\"caf\\u00e9\"");
+    }
+
+    #[test]
+    fn test_hardened() {
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct Event {
+            user: String,
+            msg: String,
+            tags: BTreeMap<String, String>,
+        }
+
+        let event = Event {
+            // looks like a bare `admin` key after a no-break space
+            user: "guest\u{A0}admin".to_owned(),
+            // Trojan Source: the comment appears to end the line
+            msg: "ok\u{202E} \u{2066}// level=error\u{2069}\u{2066}\u{9B}2K".to_owned(),
+            tags: [
+                ("ro\u{200B}le".to_owned(), "x\u{2028}level=error".to_owned()),
+                ("\u{FEFF}id".to_owned(), "\u{E0041}\u{3000}".to_owned()),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        // by default these are written as is
+        let s = crate::to_string(&event).unwrap();
+        assert!(s.starts_with("user=guest\u{A0}admin msg="));
+        assert!(s.contains('\u{202E}') && s.contains('\u{200B}') && s.contains('\u{2028}'));
+
+        let mut ser = Serializer::builder().with_hardened(true).build(Vec::new());
+        event.serialize(&mut ser).unwrap();
+        let s = String::from_utf8(ser.writer()).unwrap();
+
+        assert!(s.is_ascii());
+        assert_eq!(
+            s,
+            "user=\"guest\\u00a0admin\" \
+            msg=\"ok\\u202e \\u2066// level=error\\u2069\\u2066\\u009b2K\" \
+            tags.ro%E2%80%8Ble=\"x\\u2028level=error\" \
+            tags.%EF%BB%BFid=\"\\u{e0041}\\u3000\""
+        );
+        assert_eq!(crate::from_str::<Event>(&s).unwrap(), event);
+
+        // keys written directly are percent-encoded
+        let mut v = Vec::new();
+        let config = Serializer::builder().with_hardened(true).config();
+        Serializer::write_ident(&mut v, &config, "us\u{200B}er\u{202E}").unwrap();
+        assert_eq!(v, b"us%E2%80%8Ber%E2%80%AE");
+        let mut v = Vec::new();
+        Serializer::write_ident(&mut v, &config, "a\u{180E}b\u{E0001}\u{FE0F}").unwrap();
+        assert_eq!(v, b"a%E1%A0%8Eb%F3%A0%80%81%EF%B8%8F");
     }
 
     #[test]
@@ -874,4 +1976,960 @@ mod tests {
             "\"needs escaped \\n\""
         );
     }
+
+    #[derive(Serialize)]
+    struct Flags {
+        a: bool,
+        b: bool,
+        c: Vec<bool>,
+    }
+
+    fn with_config<T: Serialize>(config: &SerializerBuilder, v: &T) -> String {
+        let mut serializer = config.clone().build(Vec::new());
+        v.serialize(&mut serializer).unwrap();
+        String::from_utf8(serializer.writer()).unwrap()
+    }
+
+    #[test]
+    fn test_bools() {
+        let flags = Flags {
+            a: true,
+            b: false,
+            c: vec![false, true],
+        };
+
+        let bare = Serializer::builder();
+        assert_eq!(with_config(&bare, &flags), "a c.1");
+
+        let bare_true = Serializer::builder().with_bools(BoolStyle::BareTrue);
+        assert_eq!(with_config(&bare_true, &flags), "a b=false c.0=false c.1");
+
+        let explicit = Serializer::builder().with_bools(BoolStyle::Explicit);
+        assert_eq!(
+            with_config(&explicit, &flags),
+            "a=true b=false c.0=false c.1=true"
+        );
+        assert_eq!(with_config(&explicit, &false), "false");
+
+        // a bare key in a record stays bare, and bools are always explicit
+        let r: Record = [
+            ("bare", Value::Bare),
+            ("t", Value::Bool(true)),
+            ("f", Value::Bool(false)),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(with_config(&explicit, &r), "bare t=true f=false");
+        assert_eq!(with_config(&bare, &r), "bare t=true f=false");
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Empty {
+        none: Option<u8>,
+        unit: (),
+        map: BTreeMap<String, Option<u8>>,
+        seq: Vec<Option<u8>>,
+        last: u8,
+    }
+
+    #[test]
+    fn test_nulls() {
+        let empty = Empty {
+            none: None,
+            unit: (),
+            map: [("a".to_owned(), None), ("b".to_owned(), Some(1))]
+                .into_iter()
+                .collect(),
+            seq: vec![Some(2), None, Some(3)],
+            last: 4,
+        };
+
+        assert_eq!(
+            with_config(&Serializer::builder(), &empty),
+            "none=null unit= map.a=null map.b=1 seq.0=2 seq.1=null seq.2=3 last=4"
+        );
+
+        let omit = Serializer::builder()
+            .with_none(NullStyle::Omit)
+            .with_unit(NullStyle::Omit);
+        assert_eq!(with_config(&omit, &empty), "map.b=1 seq.0=2 seq.2=3 last=4");
+        assert_eq!(with_config(&omit, &None::<u8>), "");
+
+        let swapped = Serializer::builder()
+            .with_none(NullStyle::Empty)
+            .with_unit(NullStyle::Null);
+        assert_eq!(
+            with_config(&swapped, &empty),
+            "none= unit=null map.a= map.b=1 seq.0=2 seq.1= seq.2=3 last=4"
+        );
+
+        let token = Serializer::builder()
+            .with_none(NullStyle::Token("-".to_owned()))
+            .with_unit(NullStyle::Token("<no value>".to_owned()));
+        assert_eq!(
+            with_config(&token, &empty),
+            "none=- unit=\"<no value>\" map.a=- map.b=1 seq.0=2 seq.1=- seq.2=3 last=4"
+        );
+
+        for builder in [Serializer::builder(), swapped, token] {
+            let config = builder.clone().config();
+            let s = with_config(&builder, &empty);
+            let mut de = Deserializer::builder()
+                .with_none(config.none)
+                .with_unit(config.unit)
+                .build(&s);
+            assert_eq!(Empty::deserialize(&mut de).unwrap(), empty);
+        }
+
+        // an omitted top-level None leaves an empty document
+        let s = with_config(&omit, &None::<u8>);
+        let mut de = Deserializer::builder().with_none(NullStyle::Omit).build(&s);
+        assert_eq!(Option::<u8>::deserialize(&mut de).unwrap(), None);
+
+        // omitted elements leave gaps that are read back as None
+        let seq = vec![None, Some(2), None, None, Some(3)];
+        let s = with_config(&omit, &seq);
+        assert_eq!(s, "1=2 4=3");
+        assert_eq!(crate::from_str::<Vec<Option<u8>>>(&s).unwrap(), seq);
+        assert!(crate::from_str::<Vec<u8>>(&s).is_err());
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    #[serde(rename = "Lvl")]
+    enum Level {
+        Info,
+        #[serde(rename = "WARNING")]
+        Warn,
+        HTTPError,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Levels {
+        levels: [Level; 3],
+    }
+
+    #[test]
+    fn test_variants() {
+        let levels = Levels {
+            levels: [Level::Info, Level::Warn, Level::HTTPError],
+        };
+
+        for (style, expected) in [
+            (
+                VariantStyle::Qualified,
+                "levels.0=Lvl::Info levels.1=Lvl::WARNING levels.2=Lvl::HTTPError",
+            ),
+            (
+                VariantStyle::Name,
+                "levels.0=Info levels.1=WARNING levels.2=HTTPError",
+            ),
+            (
+                VariantStyle::Lowercase,
+                "levels.0=info levels.1=warning levels.2=httperror",
+            ),
+            (
+                VariantStyle::SnakeCase,
+                "levels.0=info levels.1=warning levels.2=http_error",
+            ),
+            (VariantStyle::Index, "levels.0=0 levels.1=1 levels.2=2"),
+        ] {
+            let s = with_config(&Serializer::builder().with_variants(style), &levels);
+            assert_eq!(s, expected);
+            assert_eq!(crate::from_str::<Levels>(&s).unwrap(), levels);
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Event {
+        Start,
+        Code(u128),
+        Moved(i32, i32),
+        #[serde(rename = "user")]
+        User {
+            id: u32,
+            name: String,
+        },
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Events {
+        events: Vec<Event>,
+    }
+
+    #[test]
+    fn test_tagging() {
+        let events = Events {
+            events: vec![
+                Event::Start,
+                Event::Code(389384893),
+                Event::Moved(-1, 2),
+                Event::User {
+                    id: 7,
+                    name: "a b".to_owned(),
+                },
+            ],
+        };
+
+        assert_eq!(
+            with_config(&Serializer::builder(), &events),
+            "events.0=Event::Start events.1=389384893 events.2.0=-1 events.2.1=2 \
+            events.3.id=7 events.3.name=\"a b\""
+        );
+
+        let s = with_config(
+            &Serializer::builder().with_tagging(TagStyle::External),
+            &events,
+        );
+        assert_eq!(
+            s,
+            "events.0=Event::Start events.1.Code=389384893 events.2.Moved.0=-1 events.2.Moved.1=2 \
+            events.3.user.id=7 events.3.user.name=\"a b\""
+        );
+        assert_eq!(crate::from_str::<Events>(&s).unwrap(), events);
+
+        let adjacent = TagStyle::Adjacent {
+            tag: "type".to_owned(),
+            content: "value".to_owned(),
+        };
+        let s = with_config(
+            &Serializer::builder().with_tagging(adjacent.clone()),
+            &events,
+        );
+        assert_eq!(
+            s,
+            "events.0=Event::Start events.1.type=Code events.1.value=389384893 \
+            events.2.type=Moved events.2.value.0=-1 events.2.value.1=2 \
+            events.3.type=user events.3.value.id=7 events.3.value.name=\"a b\""
+        );
+        let mut de = Deserializer::builder()
+            .with_tagging(adjacent.clone())
+            .build(&s);
+        assert_eq!(Events::deserialize(&mut de).unwrap(), events);
+
+        // a tagged variant at the top level
+        let s = with_config(
+            &Serializer::builder().with_tagging(TagStyle::External),
+            &Event::Code(5),
+        );
+        assert_eq!(s, "Code=5");
+        assert_eq!(crate::from_str::<Event>(&s).unwrap(), Event::Code(5));
+        assert_eq!(crate::from_str::<Event>("Start").unwrap(), Event::Start);
+
+        // a bare key is a unit variant or a variant holding `true`
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        enum Toggle {
+            Off,
+            On(bool),
+            Level(Option<u8>),
+        }
+
+        let external = Serializer::builder().with_tagging(TagStyle::External);
+        let s = with_config(&external, &Toggle::On(true));
+        assert_eq!(s, "On");
+        assert_eq!(crate::from_str::<Toggle>(&s).unwrap(), Toggle::On(true));
+        let s = with_config(&external, &Toggle::Off);
+        assert_eq!(s, "Toggle::Off");
+        assert_eq!(crate::from_str::<Toggle>(&s).unwrap(), Toggle::Off);
+        assert_eq!(crate::from_str::<Toggle>("Off").unwrap(), Toggle::Off);
+        assert_eq!(crate::from_str::<Toggle>("off").unwrap(), Toggle::Off);
+
+        // a quoted variant is a single value, whatever it holds
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        enum Op {
+            #[serde(rename = "a=b")]
+            Assign,
+            #[serde(rename = "a b=c")]
+            Spaced,
+        }
+
+        let names = Serializer::builder().with_variants(VariantStyle::Name);
+        for op in [Op::Assign, Op::Spaced] {
+            let s = with_config(&names, &op);
+            assert!(s.starts_with('"'), "{s}");
+            assert_eq!(crate::from_str::<Op>(&s).unwrap(), op);
+        }
+
+        // payloads that would write nothing are written out
+        let omit = Serializer::builder().with_none(NullStyle::Omit);
+        for (tagging, expected) in [
+            (TagStyle::Untagged, ["false", "null"]),
+            (TagStyle::External, ["On=false", "Level=null"]),
+            (adjacent, ["type=On value=false", "type=Level value=null"]),
+        ] {
+            let builder = omit.clone().with_tagging(tagging.clone());
+            let de = Deserializer::builder().with_tagging(tagging.clone());
+
+            for (toggle, expected) in [Toggle::On(false), Toggle::Level(None)]
+                .iter()
+                .zip(expected)
+            {
+                let s = with_config(&builder, toggle);
+                assert_eq!(s, expected);
+                if tagging != TagStyle::Untagged {
+                    let read = Toggle::deserialize(&mut de.clone().build(&s)).unwrap();
+                    assert_eq!(&read, toggle);
+                }
+            }
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Payloads<'a> {
+        #[serde(with = "serde_bytes")]
+        a: &'a [u8],
+        #[serde(with = "serde_bytes")]
+        b: &'a [u8],
+        #[serde(with = "serde_bytes")]
+        c: &'a [u8],
+    }
+
+    #[derive(Deserialize)]
+    struct OwnedPayloads {
+        #[serde(with = "serde_bytes")]
+        a: Vec<u8>,
+        #[serde(with = "serde_bytes")]
+        b: Vec<u8>,
+        #[serde(with = "serde_bytes")]
+        c: Vec<u8>,
+    }
+
+    #[test]
+    fn test_bytes() {
+        let payloads = Payloads {
+            a: &[0xFB, 0xFF, 0x43],
+            b: b"GET /",
+            c: b"",
+        };
+
+        for (style, expected) in [
+            (BytesStyle::UpperHex, "a=FBFF43 b=474554202F c="),
+            (BytesStyle::LowerHex, "a=fbff43 b=474554202f c="),
+            (BytesStyle::Base64, "a=+/9D b=\"R0VUIC8=\" c="),
+            (BytesStyle::Base64Url, "a=-_9D b=R0VUIC8 c="),
+            (BytesStyle::Utf8, "a=FBFF43 b=\"GET /\" c="),
+        ] {
+            let s = with_config(&Serializer::builder().with_bytes(style), &payloads);
+            assert_eq!(s, expected);
+
+            let mut de = Deserializer::builder().with_bytes(style).build(&s);
+            let read = OwnedPayloads::deserialize(&mut de).unwrap();
+            assert_eq!(read.b, payloads.b);
+            assert_eq!(read.c, payloads.c);
+            if style != BytesStyle::Utf8 {
+                assert_eq!(read.a, payloads.a);
+            }
+        }
+
+        let base64 = Serializer::builder().with_bytes(BytesStyle::Base64);
+        for (bytes, expected) in [
+            (&b"f"[..], "\"Zg==\""),
+            (b"fo", "\"Zm8=\""),
+            (b"foo", "Zm9v"),
+            (b"foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(
+                with_config(&base64, &serde_bytes::Bytes::new(bytes)),
+                expected
+            );
+        }
+
+        // larger than the buffers used for encoding
+        let big: Vec<u8> = (0..2000u32).map(|i| (i * 7) as u8).collect();
+        let lower = Serializer::builder().with_bytes(BytesStyle::LowerHex);
+        assert_eq!(
+            with_config(&lower, &serde_bytes::Bytes::new(&big)),
+            base16::encode_lower(&big)
+        );
+
+        let url = Serializer::builder().with_bytes(BytesStyle::Base64Url);
+        let encoded = with_config(&url, &serde_bytes::Bytes::new(&big));
+        assert_eq!(encoded.len(), (2000 * 4usize).div_ceil(3));
+        assert!(encoded.starts_with("AAcOFRwjKjE4P0ZNVFtiaXB3foWMk5qhqK"));
+
+        let mut de = Deserializer::builder()
+            .with_bytes(BytesStyle::Base64Url)
+            .build(&encoded);
+        let read = serde_bytes::ByteBuf::deserialize(&mut de).unwrap();
+        assert_eq!(read.into_vec(), big);
+
+        for (bytes, encoded) in [
+            (&b"f"[..], "\"Zg==\""),
+            (b"fo", "Zm8"),
+            (b"foo", "Zm9v"),
+            (b"A", "\"QQ==\""),
+        ] {
+            let mut de = Deserializer::builder()
+                .with_bytes(BytesStyle::Base64)
+                .build(encoded);
+            let read = serde_bytes::ByteBuf::deserialize(&mut de).unwrap();
+            assert_eq!(read.as_slice(), bytes);
+        }
+
+        for invalid in [
+            "Zg=a", "Z", "Z!==", "Zm9v=", "Zm9v==", "Zg=", "Zm8==", "Zg===", "Z===", "=", "====",
+            "Zm9v====", "Zg==Zg==", "QR==", "Zh==", "Zm9=", "Zm9",
+        ] {
+            let mut de = Deserializer::builder()
+                .with_bytes(BytesStyle::Base64)
+                .build(invalid);
+            assert!(serde_bytes::ByteBuf::deserialize(&mut de).is_err());
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Floats {
+        a: f64,
+        b: f64,
+        c: f32,
+        d: f64,
+        e: f64,
+    }
+
+    #[test]
+    fn test_floats() {
+        let floats = Floats {
+            a: 12345.678,
+            b: 1e-7,
+            c: 1.0,
+            d: -0.000123456,
+            e: 1e21,
+        };
+
+        for (style, expected) in [
+            (
+                FloatStyle::Shortest,
+                "a=12345.678 b=1e-7 c=1.0 d=-0.000123456 e=1e21",
+            ),
+            (
+                FloatStyle::Fixed(2),
+                "a=12345.68 b=0.00 c=1.00 d=-0.00 e=1000000000000000000000.00",
+            ),
+            (
+                FloatStyle::Significant(3),
+                "a=12300.0 b=0.0000001 c=1.0 d=-0.000123 e=1000000000000000000000.0",
+            ),
+            (
+                FloatStyle::Plain,
+                "a=12345.678 b=0.0000001 c=1.0 d=-0.000123456 e=1000000000000000000000.0",
+            ),
+        ] {
+            let s = with_config(&Serializer::builder().with_floats(style), &floats);
+            assert_eq!(s, expected);
+        }
+
+        // rounding up past the largest float keeps the value as it is
+        let max = Serializer::builder().with_floats(FloatStyle::Significant(3));
+        let s = with_config(&max, &f64::MAX);
+        assert!(s.starts_with("17976931348623157") && s.ends_with("0.0"));
+        assert_eq!(crate::from_str::<f64>(&s).unwrap(), f64::MAX);
+        assert_eq!(with_config(&max, &f32::MAX), format!("340{:0<36}.0", ""));
+        assert_eq!(
+            with_config(&max, &-1.797e308),
+            format!("-1797{:0<305}.0", "")
+        );
+
+        // digits out of range are clamped
+        for (digits, expected) in [(0, "100.0"), (1, "100.0"), (17, "123.456"), (40, "123.456")] {
+            let builder = Serializer::builder().with_floats(FloatStyle::Significant(digits));
+            assert_eq!(with_config(&builder, &123.456), expected);
+        }
+        let s = with_config(
+            &Serializer::builder().with_floats(FloatStyle::Significant(40)),
+            &0.1f64,
+        );
+        assert_eq!(crate::from_str::<f64>(&s).unwrap(), 0.1);
+
+        let weird = Floats {
+            a: f64::NAN,
+            b: f64::INFINITY,
+            c: f32::NEG_INFINITY,
+            d: 0.5,
+            e: 0.0,
+        };
+
+        for (style, expected) in [
+            (NonFiniteStyle::Token, "a=NaN b=inf c=-inf d=0.5 e=0.0"),
+            (NonFiniteStyle::Null, "a=null b=null c=null d=0.5 e=0.0"),
+            (NonFiniteStyle::Omit, "d=0.5 e=0.0"),
+        ] {
+            let builder = Serializer::builder()
+                .with_floats(FloatStyle::Fixed(1))
+                .with_non_finite(style);
+            assert_eq!(with_config(&builder, &weird), expected);
+        }
+
+        let mut serializer = Serializer::builder()
+            .with_non_finite(NonFiniteStyle::Error)
+            .build(Vec::new());
+        let err = weird.serialize(&mut serializer).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error from Serialize implementation: cannot write NaN at `a`"
+        );
+
+        // an omitted payload would lose the variant, so only the value is left out
+        #[derive(Serialize)]
+        enum Reading {
+            Temp(f64),
+        }
+
+        #[derive(Serialize)]
+        struct Sensor {
+            id: u8,
+            reading: Reading,
+        }
+
+        let omit = Serializer::builder().with_non_finite(NonFiniteStyle::Omit);
+        let sensor = Sensor {
+            id: 1,
+            reading: Reading::Temp(f64::NAN),
+        };
+        assert_eq!(with_config(&omit, &sensor), "id=1 reading=null");
+        let external = omit.clone().with_tagging(TagStyle::External);
+        assert_eq!(with_config(&external, &sensor), "id=1 reading.Temp=null");
+        let adjacent = omit.with_tagging(TagStyle::Adjacent {
+            tag: "t".into(),
+            content: "c".into(),
+        });
+        assert_eq!(
+            with_config(&adjacent, &sensor),
+            "id=1 reading.t=Temp reading.c=null"
+        );
+        assert_eq!(
+            with_config(&adjacent, &Reading::Temp(f64::INFINITY)),
+            "t=Temp c=null"
+        );
+    }
+
+    #[derive(Serialize)]
+    struct Tagged {
+        #[serde(rename = "")]
+        unnamed: u8,
+        tags: BTreeMap<&'static str, &'static str>,
+    }
+
+    #[test]
+    fn test_empty_keys() {
+        let tagged = Tagged {
+            unnamed: 1,
+            tags: [("", "a"), (" ", "b"), ("c", "d")].into_iter().collect(),
+        };
+
+        let placeholder = Serializer::builder()
+            .with_empty_keys(EmptyKeyStyle::Placeholder(String::from("_empty")));
+        assert_eq!(
+            with_config(&placeholder, &tagged),
+            "_empty=1 tags._empty=a tags.%20=b tags.c=d"
+        );
+
+        let stripped = placeholder.clone().with_keys(KeyStyle::Strip);
+        assert_eq!(
+            with_config(&stripped, &tagged),
+            "_empty=1 tags._empty=a tags._empty=b tags.c=d"
+        );
+
+        let skip = Serializer::builder()
+            .with_keys(KeyStyle::Strip)
+            .with_empty_keys(EmptyKeyStyle::Skip);
+        assert_eq!(with_config(&skip, &tagged), "tags.c=d");
+
+        let tagged = Tagged {
+            unnamed: 1,
+            tags: [("c", "d")].into_iter().collect(),
+        };
+        let mut serializer = Serializer::new(Vec::new());
+        let err = tagged.serialize(&mut serializer).unwrap_err();
+        assert!(matches!(&err, Error::EmptyIdentifier { key } if key.is_empty()));
+
+        let tags: BTreeMap<_, _> = [("", "a")].into_iter().collect();
+        let mut serializer = Serializer::new(Vec::new());
+        let err = BTreeMap::from([("tags", tags)])
+            .serialize(&mut serializer)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot write the key `tags.` as it has an empty identifier"
+        );
+
+        // each segment of a record key counts on its own
+        let mut record = Record::new();
+        record.insert("a", Value::Integer(1));
+        record.insert("b..c", Value::Integer(2));
+        record.insert("d.", Value::Integer(3));
+        assert_eq!(
+            with_config(&placeholder, &record),
+            "a=1 b._empty.c=2 d._empty=3"
+        );
+        assert_eq!(
+            with_config(
+                &Serializer::builder().with_empty_keys(EmptyKeyStyle::Skip),
+                &record
+            ),
+            "a=1"
+        );
+        let mut serializer = Serializer::new(Vec::new());
+        let err = record.serialize(&mut serializer).unwrap_err();
+        assert!(matches!(&err, Error::EmptyIdentifier { key } if key == "b..c"));
+    }
+
+    #[derive(Serialize)]
+    enum Shape {
+        Point,
+        Circle(u8),
+    }
+
+    #[derive(Serialize)]
+    struct Seqs {
+        nums: Vec<u8>,
+        words: [&'static str; 2],
+        flags: (bool, bool),
+        empty: Vec<u8>,
+        grid: Vec<Vec<u8>>,
+        shapes: Vec<Shape>,
+    }
+
+    #[test]
+    fn test_seqs() {
+        let seqs = Seqs {
+            nums: vec![1, 2, 3],
+            words: ["a b", "c"],
+            flags: (true, false),
+            empty: vec![],
+            grid: vec![vec![1, 2], vec![3]],
+            shapes: vec![Shape::Point, Shape::Circle(4), Shape::Point],
+        };
+
+        let exploded = Serializer::builder().with_tagging(TagStyle::External);
+        assert_eq!(
+            with_config(&exploded, &seqs),
+            "nums.0=1 nums.1=2 nums.2=3 words.0=\"a b\" words.1=c flags.0 \
+            grid.0.0=1 grid.0.1=2 grid.1.0=3 \
+            shapes.0=Shape::Point shapes.1.Circle=4 shapes.2=Shape::Point"
+        );
+
+        // nested elements fall back to exploded keys, but may be compact themselves
+        let joined = exploded.clone().with_seqs(SeqStyle::Joined(','));
+        assert_eq!(
+            with_config(&joined, &seqs),
+            "nums=1,2,3 words=\"a b,c\" flags=true,false grid.0=1,2 grid.1=3 \
+            shapes.0=Shape::Point shapes.1.Circle=4 shapes.2=Shape::Point"
+        );
+
+        let bracketed = exploded.with_seqs(SeqStyle::Bracketed);
+        assert_eq!(
+            with_config(&bracketed, &seqs),
+            "nums=[1,2,3] words=\"[a b,c]\" flags=[true,false] empty=[] \
+            grid.0=[1,2] grid.1=[3] shapes.0=Shape::Point shapes.1.Circle=4 shapes.2=Shape::Point"
+        );
+
+        let top = Serializer::builder().with_seqs(SeqStyle::Joined('|'));
+        assert_eq!(with_config(&top, &vec!["x", "y"]), "x|y");
+    }
+
+    #[test]
+    fn test_seq_elements() {
+        #[derive(Serialize)]
+        struct X<T> {
+            x: T,
+        }
+
+        #[derive(Serialize)]
+        struct In {
+            a: u8,
+        }
+
+        // elements that write nothing are left out
+        let joined = Serializer::builder().with_seqs(SeqStyle::Joined(','));
+        let omit = joined.clone().with_none(NullStyle::Omit);
+        assert_eq!(
+            with_config(
+                &omit,
+                &X {
+                    x: [Some(1), None, Some(2)]
+                }
+            ),
+            "x=1,2"
+        );
+        assert_eq!(with_config(&omit, &X { x: [None::<u8>] }), "");
+
+        let bracketed = Serializer::builder()
+            .with_seqs(SeqStyle::Bracketed)
+            .with_non_finite(NonFiniteStyle::Omit);
+        assert_eq!(
+            with_config(&bracketed, &X { x: [1.0, f64::NAN] }),
+            "x=[1.0]"
+        );
+
+        // empty structs are nested, so the sequence falls back to exploded keys
+        assert_eq!(
+            with_config(
+                &joined,
+                &X {
+                    x: [Nothing {}, Nothing {}]
+                }
+            ),
+            ""
+        );
+        let brackets = joined
+            .clone()
+            .with_empty_containers(EmptyContainerStyle::Brackets);
+        assert_eq!(
+            with_config(
+                &brackets,
+                &X {
+                    x: [Nothing {}, Nothing {}]
+                }
+            ),
+            "x.0={} x.1={}"
+        );
+        assert_eq!(
+            with_config(
+                &joined,
+                &X {
+                    x: [BTreeMap::<u8, u8>::new()]
+                }
+            ),
+            ""
+        );
+
+        // elements written before falling back keep their indices and bool style
+        assert_eq!(
+            with_config(
+                &omit,
+                &X {
+                    x: [None, Some(In { a: 1 })]
+                }
+            ),
+            "x.1.a=1"
+        );
+        assert_eq!(
+            with_config(
+                &joined,
+                &X {
+                    x: (true, false, In { a: 1 })
+                }
+            ),
+            "x.0 x.2.a=1"
+        );
+        let bare_true = joined.with_bools(BoolStyle::BareTrue);
+        assert_eq!(
+            with_config(
+                &bare_true,
+                &X {
+                    x: (true, false, In { a: 1 })
+                }
+            ),
+            "x.0 x.1=false x.2.a=1"
+        );
+
+        // strings are joined as they are and the whole value is escaped once
+        let words = X {
+            x: ["x\"y", "a b", "c\\d"],
+        };
+        let s = with_config(&bare_true, &words);
+        assert_eq!(s, r#"x="x\"y,a b,c\\d""#);
+        let read: BTreeMap<String, String> = crate::from_str(&s).unwrap();
+        assert_eq!(read["x"], "x\"y,a b,c\\d");
+        assert!(crate::from_str::<BTreeMap<String, Vec<String>>>(&s).is_err());
+
+        let lossless = bracketed.clone().with_escapes(EscapeStyle::Lossless);
+        assert_eq!(with_config(&lossless, &words), r#"x="[x\"y,a b,c\\d]""#);
+
+        // elements that couldn't be told apart once joined fail
+        let mut ser = bare_true.clone().build(Vec::new());
+        let err = X { x: ["a", "e,f"] }.serialize(&mut ser).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error from Serialize implementation: cannot write `e,f` in the compact sequence at `x`"
+        );
+        let mut ser = bracketed.build(Vec::new());
+        assert!(X { x: ["e,f"] }.serialize(&mut ser).is_err());
+        let mut ser = bare_true.clone().build(Vec::new());
+        assert!(X {
+            x: [Value::from(7), Value::from("7")]
+        }
+        .serialize(&mut ser)
+        .is_err());
+        let piped = Serializer::builder().with_seqs(SeqStyle::Joined('|'));
+        assert_eq!(with_config(&piped, &X { x: ["e,f", "g"] }), "x=e,f|g");
+
+        // and escaped on their own if the sequence falls back to exploded keys
+        assert_eq!(
+            with_config(
+                &bare_true,
+                &X {
+                    x: ("x\"y", Value::from("7"), In { a: 1 })
+                }
+            ),
+            r#"x.0="x\"y" x.1="7" x.2.a=1"#
+        );
+
+        // every element is serialized exactly once
+        struct Counted<'a>(&'a std::cell::Cell<u8>);
+
+        impl Serialize for Counted<'_> {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                self.0.set(self.0.get() + 1);
+                In { a: self.0.get() }.serialize(serializer)
+            }
+        }
+
+        let count = std::cell::Cell::new(0);
+        let counted = X {
+            x: [Counted(&count), Counted(&count)],
+        };
+        assert_eq!(with_config(&bare_true, &counted), "x.0.a=1 x.1.a=2");
+        assert_eq!(count.get(), 2);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json() {
+        #[derive(Serialize)]
+        struct Request {
+            id: u8,
+            ctx: Ctx,
+            tags: Vec<&'static str>,
+            headers: BTreeMap<&'static str, &'static str>,
+        }
+
+        #[derive(Serialize)]
+        struct Ctx {
+            user: User,
+            ip: &'static str,
+        }
+
+        #[derive(Serialize)]
+        struct User {
+            id: u8,
+            admin: bool,
+        }
+
+        let req = Request {
+            id: 1,
+            ctx: Ctx {
+                user: User {
+                    id: 3,
+                    admin: false,
+                },
+                ip: "::1",
+            },
+            tags: vec!["a", "b"],
+            headers: [("accept", "*/*")].into_iter().collect(),
+        };
+
+        let top = Serializer::builder().with_json_depth(1);
+        assert_eq!(
+            with_config(&top, &req),
+            "id=1 ctx=\"{\\\"user\\\":{\\\"id\\\":3,\\\"admin\\\":false},\\\"ip\\\":\\\"::1\\\"}\" \
+            tags=\"[\\\"a\\\",\\\"b\\\"]\" headers=\"{\\\"accept\\\":\\\"*/*\\\"}\""
+        );
+
+        let deeper = Serializer::builder().with_json_depth(2);
+        assert_eq!(
+            with_config(&deeper, &req),
+            "id=1 ctx.user=\"{\\\"id\\\":3,\\\"admin\\\":false}\" ctx.ip=::1 \
+            tags.0=a tags.1=b headers.accept=*/*"
+        );
+
+        let keys = Serializer::builder().with_json_keys(["ctx.user", "headers"]);
+        let s = with_config(&keys, &req);
+        assert_eq!(
+            s,
+            "id=1 ctx.user=\"{\\\"id\\\":3,\\\"admin\\\":false}\" ctx.ip=::1 \
+            tags.0=a tags.1=b headers=\"{\\\"accept\\\":\\\"*/*\\\"}\""
+        );
+
+        // the JSON comes back as a string
+        let record: Record = crate::from_str(&s).unwrap();
+        assert_eq!(
+            record.get("ctx.user"),
+            Some(&Value::from("{\"id\":3,\"admin\":false}"))
+        );
+
+        // the configuration doesn't apply inside the JSON
+        let floats = Serializer::builder()
+            .with_json_depth(1)
+            .with_non_finite(NonFiniteStyle::Token);
+        let m = BTreeMap::from([("f", [1.5, f64::NAN])]);
+        assert_eq!(with_config(&floats, &m), "f=[1.5,null]");
+
+        // but the JSON is always escaped losslessly
+        let inner = BTreeMap::from([("k".to_owned(), "a\"b\\c\n".to_owned())]);
+        let m = BTreeMap::from([("m", inner.clone())]);
+        for escapes in [EscapeStyle::PassThrough, EscapeStyle::Lossless] {
+            let builder = Serializer::builder()
+                .with_json_depth(1)
+                .with_escapes(escapes);
+            let s = with_config(&builder, &m);
+            let read: BTreeMap<String, String> = crate::from_str(&s).unwrap();
+            let json: BTreeMap<String, String> = serde_json::from_str(&read["m"]).unwrap();
+            assert_eq!(json, inner);
+        }
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Empties {
+        name: String,
+        tags: Vec<String>,
+        pair: (u8, u8),
+        attrs: BTreeMap<String, u8>,
+        inner: Nothing,
+        missing: Option<u8>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Nothing {}
+
+    #[test]
+    fn test_empty_containers() {
+        let empties = Empties {
+            name: String::new(),
+            tags: vec![],
+            pair: (1, 2),
+            attrs: BTreeMap::new(),
+            inner: Nothing {},
+            missing: None,
+        };
+
+        let default = Serializer::builder().with_none(NullStyle::Omit);
+        assert_eq!(with_config(&default, &empties), "name= pair.0=1 pair.1=2");
+
+        let brackets = default
+            .clone()
+            .with_quoted_empty(true)
+            .with_empty_containers(EmptyContainerStyle::Brackets);
+        let s = with_config(&brackets, &empties);
+        assert_eq!(s, "name=\"\" tags=[] pair.0=1 pair.1=2 attrs={} inner={}");
+
+        let mut de = Deserializer::from_str(&s);
+        let decoded = Empties::deserialize(&mut de).unwrap();
+        assert_eq!(decoded, empties);
+
+        let empty = default.with_empty_containers(EmptyContainerStyle::Empty);
+        let s = with_config(&empty, &empties);
+        assert_eq!(s, "name= tags= pair.0=1 pair.1=2 attrs= inner=");
+        assert_eq!(
+            Empties::deserialize(&mut Deserializer::from_str(&s)).unwrap(),
+            empties
+        );
+
+        // a compact sequence can be empty as well
+        let one_tag = Empties {
+            tags: vec![String::from("a")],
+            ..decoded
+        };
+        let joined = brackets.with_seqs(SeqStyle::Joined(','));
+        assert_eq!(
+            with_config(&joined, &one_tag),
+            "name=\"\" tags=a pair=1,2 attrs={} inner={}"
+        );
+        assert_eq!(
+            with_config(&joined, &empties),
+            "name=\"\" tags=[] pair=1,2 attrs={} inner={}"
+        );
+    }
 }
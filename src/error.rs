@@ -8,24 +8,35 @@
     error.rs: Provide a Result typedef and an Error type
 */
 
+use serde::de::Error as DeError;
 use serde::ser::Error as SerError;
 use std::fmt::Display;
 use thiserror::Error;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-/// Error type for logfmt serialization failures.
+/// Error type for logfmt serialization and deserialization failures.
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("cannot write an empty identifier")]
-    EmptyIdentifier,
+    #[error("cannot write the key `{key}` as it has an empty identifier")]
+    EmptyIdentifier { key: String },
+    #[error("cannot write the key `{key}` as it contains invalid characters")]
+    InvalidIdentifier { key: String },
     #[error("error writing to buffer")]
     WriteError {
         #[from]
         source: std::io::Error,
     },
+    #[error("error reading from buffer")]
+    ReadError { source: std::io::Error },
     #[error("error from Serialize implementation: {msg}")]
     SerializeError { msg: String },
+    #[error("error from Deserialize implementation: {msg}")]
+    DeserializeError { msg: String },
+    #[error("syntax error at byte {offset}: {reason}")]
+    SyntaxError { offset: usize, reason: &'static str },
+    #[error("line {line} is longer than the maximum of {max} bytes")]
+    LineTooLong { line: u64, max: usize },
 }
 
 impl SerError for Error {
@@ -38,3 +49,14 @@ impl SerError for Error {
         }
     }
 }
+
+impl DeError for Error {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: Display,
+    {
+        Error::DeserializeError {
+            msg: msg.to_string(),
+        }
+    }
+}
@@ -0,0 +1,439 @@
+/*
+    Copyright (C) 2023 Aurora McGinnis
+
+    This Source Code Form is subject to the terms of the Mozilla Public
+    License, v. 2.0. If a copy of the MPL was not distributed with this
+    file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+    lex.rs: Logfmt tokenizer.
+*/
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use crate::util::valid_in_ident;
+
+/// The kind of a [`Token`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    /// A key that is followed by `=` and a [`TokenKind::Value`]. The `=` is not
+    /// part of the token.
+    Key,
+    /// A key without a value, which is how the serializer writes `true`.
+    BareKey,
+    /// The value of the preceding key, including the quotes if it is quoted.
+    /// Empty values have an empty span.
+    Value,
+    /// A pair that could not be tokenized. The token spans the whole pair, while
+    /// `offset` points at the byte where tokenizing failed.
+    Error { offset: usize, reason: &'static str },
+}
+
+/// A token produced by a [`Tokenizer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token<'a> {
+    /// What the token represents.
+    pub kind: TokenKind,
+    /// The byte range of the token in the input.
+    pub span: Range<usize>,
+    /// The text of the token, i.e. `&input[span]`.
+    pub text: &'a str,
+}
+
+impl<'a> Token<'a> {
+    /// Returns true if this is a quoted value.
+    #[inline]
+    pub fn is_quoted(&self) -> bool {
+        self.kind == TokenKind::Value && self.text.starts_with('"')
+    }
+
+    /// Returns the decoded text of the token. Percent escapes are decoded in keys,
+    /// and quoted values are unquoted and unescaped. The text is borrowed from the
+    /// input whenever no decoding was necessary.
+    pub fn unescaped(&self) -> Cow<'a, str> {
+        match self.kind {
+            TokenKind::Key | TokenKind::BareKey => {
+                percent_decode(self.text).unwrap_or(Cow::Borrowed(self.text))
+            }
+            TokenKind::Value if self.is_quoted() => unescape(&self.text[1..self.text.len() - 1]),
+            _ => Cow::Borrowed(self.text),
+        }
+    }
+}
+
+/// A pull tokenizer for a single line of logfmt.
+///
+/// The tokenizer accepts the grammar produced by [`Serializer`](crate::Serializer)
+/// and yields a [`TokenKind::Key`] followed by a [`TokenKind::Value`] for every
+/// `key=value` pair and a [`TokenKind::BareKey`] for every key without a value.
+/// Malformed pairs are reported as a single [`TokenKind::Error`] token, after
+/// which tokenizing resumes at the next space.
+///
+/// ```rust
+/// use alogfmt::{TokenKind, Tokenizer};
+///
+/// let tokens: Vec<_> = Tokenizer::new("a=1 b c=\"d e\"")
+///     .map(|t| (t.kind, t.span))
+///     .collect();
+///
+/// assert_eq!(
+///     tokens,
+///     vec![
+///         (TokenKind::Key, 0..1),
+///         (TokenKind::Value, 2..3),
+///         (TokenKind::BareKey, 4..5),
+///         (TokenKind::Key, 6..7),
+///         (TokenKind::Value, 8..13),
+///     ]
+/// );
+/// ```
+pub struct Tokenizer<'a> {
+    input: &'a str,
+    pos: usize,
+    pending: Option<Token<'a>>,
+}
+
+impl<'a> Tokenizer<'a> {
+    /// Construct a new `Tokenizer` over the supplied line.
+    pub fn new(input: &'a str) -> Self {
+        Tokenizer {
+            input,
+            pos: 0,
+            pending: None,
+        }
+    }
+
+    #[inline]
+    fn token(&self, kind: TokenKind, span: Range<usize>) -> Token<'a> {
+        Token {
+            kind,
+            text: &self.input[span.clone()],
+            span,
+        }
+    }
+
+    #[inline]
+    fn char_at(&self, pos: usize) -> Option<char> {
+        self.input[pos..].chars().next()
+    }
+
+    // Returns the end of the run of identifier characters starting at `pos`.
+    fn scan_ident(&self, mut pos: usize) -> usize {
+        while let Some(ch) = self.char_at(pos) {
+            if !valid_in_ident(ch) {
+                break;
+            }
+            pos += ch.len_utf8();
+        }
+
+        pos
+    }
+
+    // Returns the position of the next space or tab at or after `pos`.
+    fn scan_to_space(&self, pos: usize) -> usize {
+        self.input[pos..]
+            .find([' ', '\t'])
+            .map_or(self.input.len(), |idx| pos + idx)
+    }
+
+    // Tokenizes the pair starting at `beg`, returning the key and value tokens and
+    // the end of the pair, or the offset and reason of the failure.
+    #[allow(clippy::type_complexity)]
+    fn scan_pair(
+        &self,
+        beg: usize,
+    ) -> std::result::Result<(Token<'a>, Option<Token<'a>>, usize), (usize, &'static str, usize)>
+    {
+        let key_end = self.scan_ident(beg);
+        if key_end == beg {
+            return Err((beg, "expected a key", self.scan_to_space(beg)));
+        }
+
+        match self.char_at(key_end) {
+            None | Some(' ' | '\t') => {
+                return Ok((self.token(TokenKind::BareKey, beg..key_end), None, key_end))
+            }
+            Some('=') => (),
+            Some(_) => {
+                return Err((
+                    key_end,
+                    "unexpected character in key",
+                    self.scan_to_space(key_end),
+                ))
+            }
+        }
+
+        let val_beg = key_end + 1;
+        let val_end = if self.char_at(val_beg) == Some('"') {
+            match scan_quoted(self.input, val_beg) {
+                Some(end) => end,
+                None => {
+                    return Err((val_beg, "unterminated quoted value", self.input.len()));
+                }
+            }
+        } else {
+            self.scan_ident(val_beg)
+        };
+
+        match self.char_at(val_end) {
+            None | Some(' ' | '\t') => Ok((
+                self.token(TokenKind::Key, beg..key_end),
+                Some(self.token(TokenKind::Value, val_beg..val_end)),
+                val_end,
+            )),
+            Some(_) => Err((
+                val_end,
+                "expected a space between pairs",
+                self.scan_to_space(val_end),
+            )),
+        }
+    }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if let Some(token) = self.pending.take() {
+            return Some(token);
+        }
+
+        let beg = self.input[self.pos..]
+            .find(|c| c != ' ' && c != '\t')
+            .map(|idx| self.pos + idx)?;
+
+        match self.scan_pair(beg) {
+            Ok((key, val, end)) => {
+                self.pos = end;
+                self.pending = val;
+                Some(key)
+            }
+            Err((offset, reason, end)) => {
+                self.pos = end;
+                Some(self.token(TokenKind::Error { offset, reason }, beg..end))
+            }
+        }
+    }
+}
+
+// Returns the end of the quoted string starting at `beg`, including the closing
+// quote, or None if it is unterminated.
+pub(crate) fn scan_quoted(input: &str, beg: usize) -> Option<usize> {
+    // The only characters that matter here are ASCII, so there is no need to
+    // decode the string. Continuation bytes can never be mistaken for them.
+    let bytes = input.as_bytes();
+    let mut end = beg + 1;
+
+    loop {
+        match bytes.get(end) {
+            Some(b'"') => return Some(end + 1),
+            Some(b'\\') => end += 2,
+            Some(_) => end += 1,
+            None => return None,
+        }
+    }
+}
+
+// Decodes the %XX escapes that `Serializer::write_ident` uses for characters
+// that are not valid in identifiers. A '%' that does not start a valid escape
+// is kept as-is. Returns None if the decoded bytes are not valid UTF-8.
+pub(crate) fn percent_decode(raw: &str) -> Option<Cow<'_, str>> {
+    if !raw.contains('%') {
+        return Some(Cow::Borrowed(raw));
+    }
+
+    let bytes = raw.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(b) = bytes.get(i + 1..i + 3).and_then(hex_byte) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8(out).ok().map(Cow::Owned)
+}
+
+#[inline]
+fn hex_byte(digits: &[u8]) -> Option<u8> {
+    let s = std::str::from_utf8(digits).ok()?;
+    u8::from_str_radix(s, 16).ok()
+}
+
+// Parses exactly `n` hex digits from the front of `s`.
+fn hex_prefix(s: &str, n: usize) -> Option<u32> {
+    let digits = s.get(..n)?;
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    u32::from_str_radix(digits, 16).ok()
+}
+
+// Resolves the escape sequences understood by `Serializer::write_val`. Escapes
+// that cannot be decoded are kept verbatim.
+pub(crate) fn unescape(raw: &str) -> Cow<'_, str> {
+    if !raw.contains('\\') {
+        return Cow::Borrowed(raw);
+    }
+
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+
+    while let Some(idx) = rest.find('\\') {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+
+        let (ch, consumed) = match rest.chars().next() {
+            Some('n') => (Some('\n'), 1),
+            Some('t') => (Some('\t'), 1),
+            Some('r') => (Some('\r'), 1),
+            Some('0') => (Some('\0'), 1),
+            Some('a') => (Some('\x07'), 1),
+            Some('b') => (Some('\x08'), 1),
+            Some('v') => (Some('\x0B'), 1),
+            Some('f') => (Some('\x0C'), 1),
+            Some('\\') => (Some('\\'), 1),
+            Some('"') => (Some('"'), 1),
+            Some('x') => match hex_prefix(&rest[1..], 2) {
+                Some(n) => (char::from_u32(n), 3),
+                None => (None, 0),
+            },
+            Some('u') if rest[1..].starts_with('{') => {
+                match rest[2..].find('}').filter(|len| (1..=6).contains(len)) {
+                    Some(len) => match hex_prefix(&rest[2..], len) {
+                        Some(n) => (char::from_u32(n), len + 3),
+                        None => (None, 0),
+                    },
+                    None => (None, 0),
+                }
+            }
+            Some('u') => match hex_prefix(&rest[1..], 4) {
+                Some(n) => (char::from_u32(n), 5),
+                None => (None, 0),
+            },
+            Some('U') => match hex_prefix(&rest[1..], 8) {
+                Some(n) => (char::from_u32(n), 9),
+                None => (None, 0),
+            },
+            _ => (None, 0),
+        };
+
+        match ch {
+            Some(ch) if consumed > 0 => {
+                out.push(ch);
+                rest = &rest[consumed..];
+            }
+            _ => out.push('\\'),
+        }
+    }
+
+    out.push_str(rest);
+
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{percent_decode, unescape, TokenKind, Tokenizer};
+    use std::ops::Range;
+
+    fn tokens(s: &str) -> Vec<(TokenKind, Range<usize>)> {
+        Tokenizer::new(s).map(|t| (t.kind, t.span)).collect()
+    }
+
+    #[test]
+    fn test_tokenizer() {
+        assert_eq!(tokens(""), vec![]);
+        assert_eq!(tokens("   "), vec![]);
+        assert_eq!(
+            tokens("nums.0=1  my_map.33 e= s=\"a \\\" b\""),
+            vec![
+                (TokenKind::Key, 0..6),
+                (TokenKind::Value, 7..8),
+                (TokenKind::BareKey, 10..19),
+                (TokenKind::Key, 20..21),
+                (TokenKind::Value, 22..22),
+                (TokenKind::Key, 23..24),
+                (TokenKind::Value, 25..33),
+            ]
+        );
+        assert_eq!(
+            tokens("a=\"b\"c d=1"),
+            vec![
+                (
+                    TokenKind::Error {
+                        offset: 5,
+                        reason: "expected a space between pairs"
+                    },
+                    0..6
+                ),
+                (TokenKind::Key, 7..8),
+                (TokenKind::Value, 9..10),
+            ]
+        );
+        assert_eq!(
+            tokens("=b a\"b c=\"unterminated d=1"),
+            vec![
+                (
+                    TokenKind::Error {
+                        offset: 0,
+                        reason: "expected a key"
+                    },
+                    0..2
+                ),
+                (
+                    TokenKind::Error {
+                        offset: 4,
+                        reason: "unexpected character in key"
+                    },
+                    3..6
+                ),
+                (
+                    TokenKind::Error {
+                        offset: 9,
+                        reason: "unterminated quoted value"
+                    },
+                    7..26
+                ),
+            ]
+        );
+
+        let t: Vec<_> = Tokenizer::new("has%20spaces=\"a\\tb\" ok=c").collect();
+        assert_eq!(t[0].unescaped(), "has spaces");
+        assert_eq!(t[1].text, "\"a\\tb\"");
+        assert!(t[1].is_quoted());
+        assert_eq!(t[1].unescaped(), "a\tb");
+        assert!(!t[3].is_quoted());
+        assert_eq!(t[3].unescaped(), "c");
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("hello").unwrap(), "hello");
+        assert_eq!(percent_decode("has%20spaces").unwrap(), "has spaces");
+        assert_eq!(percent_decode("%3Dequalsbeg").unwrap(), "=equalsbeg");
+        assert_eq!(percent_decode("100%").unwrap(), "100%");
+        assert_eq!(percent_decode("%zz").unwrap(), "%zz");
+        assert_eq!(percent_decode("%E2%90%A1").unwrap(), "␡");
+        assert!(percent_decode("%FF").is_none());
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("plain"), "plain");
+        assert_eq!(unescape("\\n \\0 \\t \\r \\\\ \\\""), "\n \0 \t \r \\ \"");
+        assert_eq!(unescape("\\x41\\u0042\\u{43}\\U0001F600"), "ABC😀");
+        assert_eq!(unescape("\\a\\b\\v\\f\\x1b"), "\x07\x08\x0B\x0C\x1B");
+        assert_eq!(unescape("\\xyz \\u{} \\q"), "\\xyz \\u{} \\q");
+        assert_eq!(unescape("trailing\\"), "trailing\\");
+    }
+}
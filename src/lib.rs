@@ -5,16 +5,32 @@
     License, v. 2.0. If a copy of the MPL was not distributed with this
     file, You can obtain one at http://mozilla.org/MPL/2.0/.
 
-    lib.rs: Export certain types and provide serde to_* functions.
+    lib.rs: Export certain types and provide serde to_* and from_* functions.
 */
+mod config;
+mod decode;
 mod encode;
 mod error;
+mod lex;
+mod read;
 mod util;
-use std::io::Write;
+mod value;
+use std::io::{Read, Write};
 
+pub use config::{
+    BoolStyle, BytesStyle, ControlStyle, DeserializerBuilder, DeserializerConfig,
+    EmptyContainerStyle, EmptyKeyStyle, EscapeStyle, FloatStyle, IndexStyle, KeyStyle,
+    NonFiniteStyle, NullStyle, SeqStyle, SerializerBuilder, SerializerConfig, TagStyle,
+    VariantStyle,
+};
+pub use decode::{Deserializer, Inference, Recovery};
 pub use encode::Serializer;
 pub use error::{Error, Result};
+pub use lex::{Token, TokenKind, Tokenizer};
+pub use read::{Reader, RecordIter};
+use serde::de::{Deserialize, DeserializeOwned};
 use serde::ser::Serialize;
+pub use value::{Record, Value};
 
 /// Serializes an object to logfmt and returns the result as a string.
 ///
@@ -53,13 +69,50 @@ pub fn to_writer<T: Serialize, W: Write>(dst: &mut W, src: &T) -> Result<()> {
     Ok(())
 }
 
+/// Deserializes an object from a logfmt-encoded string.
+///
+/// # Errors
+/// This function will return an error if the input is not valid logfmt or
+/// the `Deserialize` implementation on T fails.
+pub fn from_str<'a, T: Deserialize<'a>>(s: &'a str) -> Result<T> {
+    let mut deserializer = Deserializer::from_str(s);
+    T::deserialize(&mut deserializer)
+}
+
+/// Deserializes an object from logfmt-encoded bytes.
+///
+/// # Errors
+/// This function will return an error if the input is not valid UTF-8, is not
+/// valid logfmt, or the `Deserialize` implementation on T fails.
+pub fn from_slice<'a, T: Deserialize<'a>>(v: &'a [u8]) -> Result<T> {
+    let mut deserializer = Deserializer::from_slice(v)?;
+    T::deserialize(&mut deserializer)
+}
+
+/// Deserializes an object from logfmt read from the provided reader.
+///
+/// The entire reader is consumed and treated as a single document.
+///
+/// # Errors
+/// This function will return an error if the underlying reader encounters an
+/// IO error, the input is not valid logfmt, or the `Deserialize` implementation
+/// on T fails.
+pub fn from_reader<R: Read, T: DeserializeOwned>(mut src: R) -> Result<T> {
+    let mut buf = String::with_capacity(256);
+    src.read_to_string(&mut buf)
+        .map_err(|source| Error::ReadError { source })?;
+
+    from_str(&buf)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::to_writer;
+    use crate::{from_reader, from_slice, from_str, to_writer};
 
     use super::to_string;
-    use serde::Serialize;
-    use std::collections::HashMap;
+    use serde::{Deserialize, Serialize};
+    use std::borrow::Cow;
+    use std::collections::{BTreeMap, HashMap};
 
     // Some types to play with
     #[derive(Serialize)]
@@ -140,4 +193,150 @@ mod tests {
             "message=\"hello world\" integer=3829 enum_val=MyEnum::Variant2 b=FF014364 nums.0=1 nums.1=2 nums.2=3 nums.3=4 my_map.33"
         );
     }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct FlatStruct {
+        message: String,
+        integer: i64,
+        float: f64,
+        level: Level,
+        flag: bool,
+        missing: Option<u8>,
+        nothing: (),
+        #[serde(with = "serde_bytes")]
+        b: Vec<u8>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    enum Level {
+        Info,
+        Warn,
+    }
+
+    #[test]
+    fn deserialize_flat_struct() {
+        let my_struct = FlatStruct {
+            message: String::from("hello \"world\"\n"),
+            integer: -3829,
+            float: 1.5,
+            level: Level::Warn,
+            flag: true,
+            missing: None,
+            nothing: (),
+            b: vec![0xFF, 0x01, 0x43, 0x64],
+        };
+
+        let s = to_string(&my_struct).unwrap();
+        assert_eq!(
+            s,
+            "message=\"hello \\\"world\\\"\\n\" integer=-3829 float=1.5 level=Level::Warn flag missing=null nothing= b=FF014364"
+        );
+
+        assert_eq!(from_str::<FlatStruct>(&s).unwrap(), my_struct);
+        assert_eq!(from_slice::<FlatStruct>(s.as_bytes()).unwrap(), my_struct);
+        assert_eq!(
+            from_reader::<_, FlatStruct>(format!("{s}\n").as_bytes()).unwrap(),
+            my_struct
+        );
+
+        let m: HashMap<String, String> = from_str("has%20spaces=1 with%3Dequals=\"a b\"").unwrap();
+        assert_eq!(m["has spaces"], "1");
+        assert_eq!(m["with=equals"], "a b");
+
+        assert_eq!(from_str::<u64>("1690232215").unwrap(), 1690232215);
+        assert_eq!(
+            from_str::<String>("\"hello world\"").unwrap(),
+            "hello world"
+        );
+        assert_eq!(from_str::<Option<String>>("null").unwrap(), None);
+        assert!(from_str::<FlatStruct>("message=\"unterminated").is_err());
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct NestedStruct {
+        id: u32,
+        nums: Vec<i32>,
+        arr: [u8; 3],
+        pair: (String, f32),
+        inner: Inner,
+        items: Vec<Inner>,
+        maybe: Option<Inner>,
+        map: BTreeMap<String, Inner>,
+        counts: BTreeMap<u16, u64>,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Inner {
+        name: String,
+        flag: bool,
+    }
+
+    #[test]
+    fn deserialize_nested_struct() {
+        let inner = |name: &str| Inner {
+            name: name.to_owned(),
+            flag: true,
+        };
+
+        let my_struct = NestedStruct {
+            id: 7,
+            nums: vec![1, 2, 3, 4],
+            arr: [9, 8, 7],
+            pair: ("a b".to_owned(), 0.25),
+            inner: inner("x"),
+            items: vec![inner("y"), inner("z")],
+            maybe: Some(inner("m")),
+            map: [
+                ("k1".to_owned(), inner("v1")),
+                ("k 2".to_owned(), inner("v2")),
+            ]
+            .into_iter()
+            .collect(),
+            counts: [(33, 1), (34, 2)].into_iter().collect(),
+        };
+
+        let s = to_string(&my_struct).unwrap();
+        assert_eq!(
+            s,
+            "id=7 nums.0=1 nums.1=2 nums.2=3 nums.3=4 arr.0=9 arr.1=8 arr.2=7 pair.0=\"a b\" pair.1=0.25 \
+            inner.name=x inner.flag items.0.name=y items.0.flag items.1.name=z items.1.flag \
+            maybe.name=m maybe.flag map.k%202.name=v2 map.k%202.flag map.k1.name=v1 map.k1.flag \
+            counts.33=1 counts.34=2"
+        );
+        assert_eq!(from_str::<NestedStruct>(&s).unwrap(), my_struct);
+
+        assert_eq!(from_str::<Vec<u8>>("0=1 1=2").unwrap(), vec![1, 2]);
+        assert!(from_str::<Vec<u8>>("0=1 2=2").is_err());
+        assert!(from_str::<HashMap<String, u8>>("a=1 a.b=2").is_err());
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct BorrowedStruct<'a> {
+        msg: &'a str,
+        #[serde(borrow)]
+        plain: Cow<'a, str>,
+        #[serde(borrow)]
+        escaped: Cow<'a, str>,
+        #[serde(borrow)]
+        tags: HashMap<Cow<'a, str>, &'a str>,
+    }
+
+    #[test]
+    fn deserialize_borrowed() {
+        let s = "msg=\"hello world\" plain=ident escaped=\"a\\tb\" tags.k%201=v1 tags.k2=\"v 2\"";
+        let b: BorrowedStruct = from_str(s).unwrap();
+
+        assert_eq!(b.msg, "hello world");
+        assert!(matches!(b.plain, Cow::Borrowed("ident")));
+        assert!(matches!(b.escaped, Cow::Owned(ref e) if e == "a\tb"));
+        assert!(matches!(b.tags.get("k 1"), Some(&"v1")));
+        assert!(matches!(b.tags.get("k2"), Some(&"v 2")));
+        assert!(b.tags.keys().any(|k| matches!(k, Cow::Owned(_))));
+
+        let b: BorrowedStruct = from_slice(s.as_bytes()).unwrap();
+        assert_eq!(b.msg, "hello world");
+
+        // &str cannot hold a value that had to be unescaped
+        assert!(from_str::<BorrowedStruct>("msg=\"a\\nb\" plain= escaped=").is_err());
+    }
 }
@@ -0,0 +1,341 @@
+/*
+    Copyright (C) 2023 Aurora McGinnis
+
+    This Source Code Form is subject to the terms of the Mozilla Public
+    License, v. 2.0. If a copy of the MPL was not distributed with this
+    file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+    read.rs: Line-oriented logfmt reader.
+*/
+
+use serde::de::DeserializeOwned;
+use std::io::{BufRead, ErrorKind};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::config::DeserializerConfig;
+use crate::decode::Deserializer;
+use crate::error::{Error, Result};
+use crate::value::Record;
+
+/// Reads logfmt documents, one per line, from a `BufRead`.
+///
+/// This is the counterpart to writing several documents with `serializer.next()`.
+/// Blank lines are skipped. Lines longer than the maximum line length (1 MiB by
+/// default) are discarded without being buffered and reported as an
+/// [`Error::LineTooLong`], after which reading continues with the next line.
+///
+/// ```rust
+/// use alogfmt::{Reader, Record};
+/// use anyhow::Result;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct MyStruct {
+///    pub ts: u64,
+///    pub message: String,
+/// }
+///
+/// fn main() -> Result<()> {
+///    let input = "ts=1690232215 message=\"Hello World!\"\nts=1690232216 message=Goodbye\n";
+///    let mut reader = Reader::new(input.as_bytes());
+///
+///    let mut count = 0;
+///    for s in reader.deserialize::<MyStruct>() {
+///        let s = s?;
+///        assert!(s.ts >= 1690232215);
+///        count += 1;
+///    }
+///
+///    assert_eq!(count, 2);
+///
+///    Ok(())
+/// }
+/// ```
+pub struct Reader<R> {
+    r: R,
+    buf: Vec<u8>,
+    line: u64,
+    max_line_len: usize,
+    config: Arc<DeserializerConfig>,
+    failed: bool,
+}
+
+impl<R> Reader<R>
+where
+    R: BufRead,
+{
+    /// Construct a new `Reader` that reads from the
+    /// supplied object implementing `BufRead`.
+    pub fn new(reader: R) -> Self {
+        Reader {
+            r: reader,
+            buf: Vec::with_capacity(256),
+            line: 0,
+            max_line_len: 1024 * 1024,
+            config: Arc::new(DeserializerConfig::default()),
+            failed: false,
+        }
+    }
+
+    /// Sets the maximum length of a line in bytes, excluding the line ending.
+    #[inline]
+    pub fn with_max_line_len(mut self, max_line_len: usize) -> Self {
+        self.max_line_len = max_line_len;
+        self
+    }
+
+    /// Deserializes each line with the given configuration. Pass an
+    /// `Arc<DeserializerConfig>` to share one configuration between readers.
+    #[inline]
+    pub fn with_config<C>(mut self, config: C) -> Self
+    where
+        C: Into<Arc<DeserializerConfig>>,
+    {
+        self.config = config.into();
+        self
+    }
+
+    /// Returns the number of the line that was read last, starting at 1.
+    #[inline]
+    pub fn line(&self) -> u64 {
+        self.line
+    }
+
+    /// Reclaim the reader wrapped by this `Reader`.
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+
+    /// Reads the next non-blank line and deserializes it as a `T`. Returns
+    /// `None` once the end of the input is reached.
+    ///
+    /// # Errors
+    /// This function will return an error if the underlying reader encounters an
+    /// IO error, the line is too long, the line is not valid logfmt, or the
+    /// `Deserialize` implementation on T fails. Reading may continue after any
+    /// error except an IO error.
+    pub fn read<T: DeserializeOwned>(&mut self) -> Option<Result<T>> {
+        loop {
+            match self.read_line() {
+                Ok(true) if self.buf.iter().all(u8::is_ascii_whitespace) => continue,
+                Ok(true) => break,
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+
+        let res = Deserializer::from_slice(&self.buf).and_then(|deserializer| {
+            let mut deserializer = deserializer.with_config(Arc::clone(&self.config));
+            T::deserialize(&mut deserializer)
+        });
+
+        Some(res)
+    }
+
+    /// Returns an iterator that deserializes each line as a `T`.
+    #[inline]
+    pub fn deserialize<T: DeserializeOwned>(&mut self) -> RecordIter<'_, R, T> {
+        RecordIter {
+            reader: self,
+            _t: PhantomData,
+        }
+    }
+
+    /// Returns an iterator that reads each line as a [`Record`].
+    #[inline]
+    pub fn records(&mut self) -> RecordIter<'_, R, Record> {
+        self.deserialize()
+    }
+
+    // Reads the next line into self.buf. Returns false at the end of the input.
+    fn read_line(&mut self) -> Result<bool> {
+        if self.failed {
+            return Ok(false);
+        }
+
+        self.buf.clear();
+        let mut read_any = false;
+        let mut too_long = false;
+        let mut ended = false;
+
+        loop {
+            let available = match self.r.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(source) => {
+                    self.failed = true;
+                    return Err(Error::ReadError { source });
+                }
+            };
+
+            if available.is_empty() {
+                break;
+            }
+            read_any = true;
+
+            let (len, content, done) = match available.iter().position(|b| *b == b'\n') {
+                Some(idx) => (idx + 1, &available[..idx], true),
+                None => (available.len(), available, false),
+            };
+
+            // Keep discarding the rest of a line that is too long
+            if !too_long {
+                // A trailing `\r` belongs to the line ending if a `\n` follows,
+                // which may only arrive with the next read
+                let content_len = self.buf.len() + content.len();
+                let content_len = match content.last().or(self.buf.last()) {
+                    Some(b'\r') => content_len - 1,
+                    _ => content_len,
+                };
+
+                if content_len > self.max_line_len {
+                    too_long = true;
+                    self.buf.clear();
+                } else {
+                    self.buf.extend_from_slice(&available[..len]);
+                }
+            }
+
+            self.r.consume(len);
+
+            if done {
+                ended = true;
+                break;
+            }
+        }
+
+        if !read_any {
+            return Ok(false);
+        }
+
+        // The last line may end in a `\r` that no `\n` followed
+        if !ended && self.buf.len() > self.max_line_len {
+            too_long = true;
+        }
+
+        self.line += 1;
+
+        if too_long {
+            return Err(Error::LineTooLong {
+                line: self.line,
+                max: self.max_line_len,
+            });
+        }
+
+        Ok(true)
+    }
+}
+
+/// An iterator over the documents read by a [`Reader`].
+pub struct RecordIter<'r, R, T> {
+    reader: &'r mut Reader<R>,
+    _t: PhantomData<T>,
+}
+
+impl<R, T> Iterator for RecordIter<'_, R, T>
+where
+    R: BufRead,
+    T: DeserializeOwned,
+{
+    type Item = Result<T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Result<T>> {
+        self.reader.read()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Reader;
+    use crate::decode::{Deserializer, Recovery};
+    use crate::error::Error;
+    use crate::value::{Record, Value};
+    use crate::Serializer;
+    use serde::{Deserialize, Serialize};
+    use std::io::BufReader;
+    use std::sync::Arc;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct MyStruct {
+        ts: u64,
+        message: String,
+    }
+
+    #[test]
+    fn test_read_serialized() {
+        let mut serializer = Serializer::new(Vec::new());
+        for ts in 0..3 {
+            let s = MyStruct {
+                ts,
+                message: format!("message {ts}"),
+            };
+            s.serialize(&mut serializer).unwrap();
+            serializer.next().unwrap();
+        }
+
+        let v = serializer.writer();
+        let mut reader = Reader::new(BufReader::with_capacity(7, &v[..]));
+        let read: Vec<MyStruct> = reader.deserialize().map(|r| r.unwrap()).collect();
+
+        assert_eq!(read.len(), 3);
+        for (ts, s) in read.into_iter().enumerate() {
+            assert_eq!(s.ts, ts as u64);
+            assert_eq!(s.message, format!("message {ts}"));
+        }
+        assert_eq!(reader.line(), 3);
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let input = "a=1\r\n\n  \nb=\"open\nc=0123456789\r\nd=2";
+        let mut reader =
+            Reader::new(BufReader::with_capacity(4, input.as_bytes())).with_max_line_len(10);
+
+        let r = reader.records().next().unwrap().unwrap();
+        assert_eq!(r.get("a"), Some(&Value::Integer(1)));
+
+        assert!(matches!(
+            reader.read::<Record>(),
+            Some(Err(Error::SyntaxError { offset: 2, .. }))
+        ));
+        assert_eq!(reader.line(), 4);
+
+        assert!(matches!(
+            reader.read::<Record>(),
+            Some(Err(Error::LineTooLong { line: 5, max: 10 }))
+        ));
+
+        let r = reader.read::<Record>().unwrap().unwrap();
+        assert_eq!(r.get("d"), Some(&Value::Integer(2)));
+        assert!(reader.read::<Record>().is_none());
+
+        // exactly at the limit, ignoring the line ending
+        let mut reader = Reader::new("c=01234567\r\n".as_bytes()).with_max_line_len(10);
+        let r = reader.read::<Record>().unwrap().unwrap();
+        assert_eq!(r.get("c"), Some(&Value::from("01234567")));
+
+        // even when the line ending is split between reads
+        let input = BufReader::with_capacity(11, "c=01234567\r\nd=0123456789\r".as_bytes());
+        let mut reader = Reader::new(input).with_max_line_len(10);
+        let r = reader.read::<Record>().unwrap().unwrap();
+        assert_eq!(r.get("c"), Some(&Value::from("01234567")));
+        assert!(matches!(
+            reader.read::<Record>(),
+            Some(Err(Error::LineTooLong { line: 2, max: 10 }))
+        ));
+
+        // readers can share one configuration
+        let config = Arc::new(
+            Deserializer::builder()
+                .with_recovery(Recovery::Skip)
+                .config(),
+        );
+        for input in ["a=1 b=\"open\n", "a=1 =2\n"] {
+            let mut reader = Reader::new(input.as_bytes()).with_config(Arc::clone(&config));
+            let r = reader.read::<Record>().unwrap().unwrap();
+            assert_eq!(r.iter().collect::<Vec<_>>(), [("a", &Value::Integer(1))]);
+        }
+    }
+}
@@ -8,6 +8,203 @@
     util.rs: Utility functions
 */
 
+use std::io::{self, Write};
+
+pub(crate) const HEX_UPPER: &[u8; 16] = b"0123456789ABCDEF";
+pub(crate) const HEX_LOWER: &[u8; 16] = b"0123456789abcdef";
+pub(crate) const BASE64: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+pub(crate) const BASE64_URL: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Returns true if the character may appear unescaped in a logfmt identifier.
+#[inline]
+pub(crate) fn valid_in_ident(c: char) -> bool {
+    c > ' ' && c != '=' && c != '"' && !c.is_control()
+}
+
+/// Returns true if the character can make a line look different from what it
+/// contains: whitespace other than ASCII space, line and paragraph separators,
+/// invisible format characters and bidirectional text controls, as well as
+/// invisible characters outside the format category such as variation
+/// selectors and Hangul fillers.
+pub(crate) fn is_deceptive(c: char) -> bool {
+    (c.is_whitespace() && c != ' ')
+        || is_format(c)
+        || matches!(
+            c,
+            '\u{34F}'
+                | '\u{115F}'..='\u{1160}'
+                | '\u{17B4}'..='\u{17B5}'
+                | '\u{180B}'..='\u{180D}'
+                | '\u{180F}'
+                | '\u{2065}'
+                | '\u{3164}'
+                | '\u{FE00}'..='\u{FE0F}'
+                | '\u{FFA0}'
+                | '\u{E0000}'
+                | '\u{E0002}'..='\u{E001F}'
+                | '\u{E0080}'..='\u{E0FFF}'
+        )
+}
+
+/// Returns true if the character is an invisible format character, i.e. in the
+/// Unicode general category Cf, such as soft hyphens, zero width spaces and
+/// bidirectional text controls.
+pub(crate) fn is_format(c: char) -> bool {
+    matches!(
+        c,
+        '\u{AD}'
+            | '\u{600}'..='\u{605}'
+            | '\u{61C}'
+            | '\u{6DD}'
+            | '\u{70F}'
+            | '\u{890}'..='\u{891}'
+            | '\u{8E2}'
+            | '\u{180E}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{206F}'
+            | '\u{FEFF}'
+            | '\u{FFF9}'..='\u{FFFB}'
+            | '\u{110BD}'
+            | '\u{110CD}'
+            | '\u{13430}'..='\u{1343F}'
+            | '\u{1BCA0}'..='\u{1BCA3}'
+            | '\u{1D173}'..='\u{1D17A}'
+            | '\u{E0001}'
+            | '\u{E0020}'..='\u{E007F}'
+    )
+}
+
+/// Converts a `CamelCase` identifier to `snake_case`. Runs of capitals are kept
+/// together, so both `HttpServer` and `HTTPServer` become `http_server`.
+pub(crate) fn to_snake_case(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 4);
+    let mut chars = s.chars().peekable();
+    let mut prev: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        if c.is_uppercase() {
+            let boundary = match prev {
+                Some(p) if p.is_lowercase() || p.is_ascii_digit() => true,
+                Some(p) if p.is_uppercase() => chars.peek().is_some_and(|n| n.is_lowercase()),
+                _ => false,
+            };
+
+            if boundary {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+
+        prev = Some(c);
+    }
+
+    out
+}
+
+/// Hex encodes `bytes` into `w` using the given digits, without allocating.
+pub(crate) fn write_hex<W: Write>(w: &mut W, bytes: &[u8], digits: &[u8; 16]) -> io::Result<()> {
+    let mut buf = [0u8; 512];
+
+    for chunk in bytes.chunks(buf.len() / 2) {
+        for (b, out) in chunk.iter().zip(buf.chunks_exact_mut(2)) {
+            out[0] = digits[(b >> 4) as usize];
+            out[1] = digits[(b & 0xF) as usize];
+        }
+
+        w.write_all(&buf[..chunk.len() * 2])?;
+    }
+
+    Ok(())
+}
+
+/// Base64 encodes `bytes` into `w` using the given alphabet, without allocating.
+/// If `pad` is true, the output is padded with `=` to a multiple of four.
+pub(crate) fn write_base64<W: Write>(
+    w: &mut W,
+    bytes: &[u8],
+    alphabet: &[u8; 64],
+    pad: bool,
+) -> io::Result<()> {
+    let mut buf = [0u8; 512];
+
+    for chunk in bytes.chunks(buf.len() / 4 * 3) {
+        let mut len = 0;
+
+        for group in chunk.chunks(3) {
+            let n = match *group {
+                [a, b, c] => u32::from_be_bytes([0, a, b, c]),
+                [a, b] => u32::from_be_bytes([0, a, b, 0]),
+                [a] => u32::from_be_bytes([0, a, 0, 0]),
+                _ => unreachable!("chunks(3) yields one to three bytes"),
+            };
+
+            let out = &mut buf[len..len + 4];
+            for (i, o) in out.iter_mut().enumerate() {
+                *o = alphabet[(n >> (18 - 6 * i) & 0x3F) as usize];
+            }
+
+            // one output char per six input bits, rounded up
+            let used = (group.len() * 8).div_ceil(6);
+            if pad {
+                out[used..].fill(b'=');
+                len += 4;
+            } else {
+                len += used;
+            }
+        }
+
+        w.write_all(&buf[..len])?;
+    }
+
+    Ok(())
+}
+
+/// Decodes base64 in the given alphabet, as written by `write_base64` with or
+/// without padding. Returns `None` if `s` is not valid base64, including if it
+/// is padded anywhere but at the end of its last group of four.
+pub(crate) fn decode_base64(s: &[u8], alphabet: &[u8; 64]) -> Option<Vec<u8>> {
+    let mut table = [u8::MAX; 256];
+    for (i, c) in alphabet.iter().enumerate() {
+        table[*c as usize] = i as u8;
+    }
+
+    let pad = s.iter().rev().take_while(|c| **c == b'=').count();
+    let s = &s[..s.len() - pad];
+    // padding fills out the last group of four, which holds at least two chars
+    if s.len() % 4 == 1 || pad > 2 || (pad > 0 && (s.len() + pad) % 4 != 0) {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(s.len() / 4 * 3 + 2);
+
+    for group in s.chunks(4) {
+        let mut n: u32 = 0;
+
+        for (i, c) in group.iter().enumerate() {
+            match table[*c as usize] {
+                u8::MAX => return None,
+                v => n |= u32::from(v) << (18 - 6 * i),
+            }
+        }
+
+        // each group of four chars holds three bytes, a shorter one fewer, and
+        // leaves the bits past its last byte unset
+        let bytes = n.to_be_bytes();
+        if bytes[group.len()..].iter().any(|b| *b != 0) {
+            return None;
+        }
+        out.extend_from_slice(&bytes[1..group.len()]);
+    }
+
+    Some(out)
+}
+
 /// Given a ASCII control character, space, or DEL character, return its corresponding unicode photo.
 pub(crate) fn as_control_picture(ch: char) -> Option<char> {
     match ch {
@@ -0,0 +1,633 @@
+/*
+    Copyright (C) 2023 Aurora McGinnis
+
+    This Source Code Form is subject to the terms of the Mozilla Public
+    License, v. 2.0. If a copy of the MPL was not distributed with this
+    file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+    value.rs: Dynamically typed logfmt records.
+*/
+
+use serde::de::{self, Deserialize, Deserializer, IgnoredAny, MapAccess, Unexpected, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::borrow::Cow;
+use std::fmt;
+
+// Value and Record use these names to ask the logfmt Serializer and Deserializer
+// for behavior that other formats have no equivalent of. Other formats treat them
+// like any other newtype struct.
+pub(crate) const VALUE_TOKEN: &str = "$alogfmt::private::Value";
+pub(crate) const RECORD_TOKEN: &str = "$alogfmt::private::Record";
+pub(crate) const BARE_TOKEN: &str = "$alogfmt::private::Bare";
+pub(crate) const BOOL_TOKEN: &str = "$alogfmt::private::Bool";
+pub(crate) const STRING_TOKEN: &str = "$alogfmt::private::String";
+
+/// A dynamically typed logfmt value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A key without a value (`key`).
+    Bare,
+    /// `key=null`
+    Null,
+    Bool(bool),
+    Integer(i128),
+    Float(f64),
+    /// `key="value"`, always quoted when written by the logfmt `Serializer`.
+    String(String),
+}
+
+impl Value {
+    /// Returns true if the value is `Null`.
+    #[inline]
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Returns the value as a bool. Bare keys are `true`.
+    #[inline]
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bare => Some(true),
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is an `Integer`.
+    #[inline]
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is a `Float` or an `Integer`.
+    #[inline]
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Float(f) => Some(*f),
+            Value::Integer(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+
+    /// Returns the value if it is a `String`.
+    #[inline]
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! value_from_int {
+    ($($ty:ty)*) => {
+        $(
+            impl From<$ty> for Value {
+                #[inline]
+                fn from(v: $ty) -> Self {
+                    Value::Integer(v.into())
+                }
+            }
+        )*
+    };
+}
+
+value_from_int! { i8 i16 i32 i64 i128 u8 u16 u32 u64 }
+
+impl From<bool> for Value {
+    #[inline]
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<f32> for Value {
+    #[inline]
+    fn from(v: f32) -> Self {
+        Value::Float(v.into())
+    }
+}
+
+impl From<f64> for Value {
+    #[inline]
+    fn from(v: f64) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<&str> for Value {
+    #[inline]
+    fn from(v: &str) -> Self {
+        Value::String(v.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    #[inline]
+    fn from(v: String) -> Self {
+        Value::String(v)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    #[inline]
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Value::Bare => serializer.serialize_newtype_struct(BARE_TOKEN, &true),
+            Value::Null => serializer.serialize_none(),
+            Value::Bool(b) => serializer.serialize_newtype_struct(BOOL_TOKEN, b),
+            Value::Integer(i) => {
+                // Prefer the narrower types, which more formats support
+                if let Ok(v) = i64::try_from(*i) {
+                    serializer.serialize_i64(v)
+                } else if let Ok(v) = u64::try_from(*i) {
+                    serializer.serialize_u64(v)
+                } else {
+                    serializer.serialize_i128(*i)
+                }
+            }
+            Value::Float(f) => serializer.serialize_f64(*f),
+            // quoted even if it looks like a number, a bool or null, so that it
+            // isn't inferred to be one when read back
+            Value::String(s) => serializer.serialize_newtype_struct(STRING_TOKEN, s),
+        }
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a logfmt value")
+    }
+
+    #[inline]
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    #[inline]
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    #[inline]
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<Value, E> {
+        Ok(Value::Integer(v))
+    }
+
+    #[inline]
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::Integer(v.into()))
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<Value, E> {
+        match i128::try_from(v) {
+            Ok(v) => Ok(Value::Integer(v)),
+            Err(_) => Err(de::Error::invalid_value(
+                Unexpected::Other("integer out of range"),
+                &self,
+            )),
+        }
+    }
+
+    #[inline]
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Float(v))
+    }
+
+    #[inline]
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.to_owned()))
+    }
+
+    #[inline]
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Value, E> {
+        Ok(Value::String(v))
+    }
+
+    #[inline]
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    #[inline]
+    fn visit_some<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Value::deserialize(deserializer)
+    }
+
+    #[inline]
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    #[inline]
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+
+    // The logfmt Deserializer reports bare keys as a map holding BARE_TOKEN
+    fn visit_map<A>(self, mut map: A) -> Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        match map.next_key::<Cow<str>>()? {
+            Some(key) if key == BARE_TOKEN => {
+                map.next_value::<IgnoredAny>()?;
+                Ok(Value::Bare)
+            }
+            _ => Err(de::Error::invalid_type(Unexpected::Map, &self)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(VALUE_TOKEN, ValueVisitor)
+    }
+}
+
+/// A dynamically typed logfmt record: an ordered list of keys and values.
+///
+/// Keys are the full, dotted key of each pair, so nested values are never
+/// grouped together when a `Record` is deserialized. Keys may repeat. When a
+/// `Record` is serialized, the segments of each key are joined with the
+/// configured separator, and segments that are numbers are written as indices.
+/// A `.` or `%` within a segment, such as a map key holding a dot, is escaped
+/// as `%2E` or `%25`.
+///
+/// ```rust
+/// use alogfmt::{from_str, to_string, Record, Value};
+/// use anyhow::Result;
+///
+/// fn main() -> Result<()> {
+///    let mut record: Record = from_str("ts=1690232215 user.name=aurora debug")?;
+///
+///    assert_eq!(record.get("debug"), Some(&Value::Bare));
+///
+///    record.remove("user.name");
+///    record.insert("message", "Hello World!");
+///
+///    assert_eq!(
+///        to_string(&record)?,
+///        "ts=1690232215 debug message=\"Hello World!\""
+///    );
+///
+///    Ok(())
+/// }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Record {
+    pairs: Vec<(String, Value)>,
+}
+
+impl Record {
+    /// Construct a new, empty `Record`.
+    #[inline]
+    pub fn new() -> Self {
+        Record { pairs: Vec::new() }
+    }
+
+    /// Returns the number of pairs in the record.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Returns true if the record has no pairs.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Returns the value of the first pair with the given key.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// Returns a mutable reference to the value of the first pair with the given key.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        self.pairs
+            .iter_mut()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    /// Returns true if the record has a pair with the given key.
+    #[inline]
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Replaces the value of the first pair with the given key, returning the
+    /// old value. If there is no such pair, one is appended.
+    pub fn insert<K, V>(&mut self, key: K, val: V) -> Option<Value>
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        let key = key.into();
+        let val = val.into();
+
+        match self.get_mut(&key) {
+            Some(v) => Some(std::mem::replace(v, val)),
+            None => {
+                self.pairs.push((key, val));
+                None
+            }
+        }
+    }
+
+    /// Appends a pair, even if a pair with the same key exists.
+    #[inline]
+    pub fn push<K, V>(&mut self, key: K, val: V)
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        self.pairs.push((key.into(), val.into()));
+    }
+
+    /// Removes every pair with the given key, returning the first value.
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let mut removed = None;
+
+        self.pairs.retain_mut(|(k, v)| {
+            if k != key {
+                return true;
+            }
+
+            if removed.is_none() {
+                removed = Some(std::mem::replace(v, Value::Null));
+            }
+            false
+        });
+
+        removed
+    }
+
+    /// Returns an iterator over the pairs in order.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Returns an iterator over the pairs in order, with mutable values.
+    #[inline]
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut Value)> {
+        self.pairs.iter_mut().map(|(k, v)| (k.as_str(), v))
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> FromIterator<(K, V)> for Record {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Record {
+            pairs: iter
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        }
+    }
+}
+
+impl<K: Into<String>, V: Into<Value>> Extend<(K, V)> for Record {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.pairs
+            .extend(iter.into_iter().map(|(k, v)| (k.into(), v.into())));
+    }
+}
+
+impl IntoIterator for Record {
+    type Item = (String, Value);
+    type IntoIter = std::vec::IntoIter<(String, Value)>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.pairs.into_iter()
+    }
+}
+
+impl Serialize for Record {
+    #[inline]
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(RECORD_TOKEN, &Pairs(&self.pairs))
+    }
+}
+
+// The pairs of a Record, serialized as a map.
+struct Pairs<'a>(&'a [(String, Value)]);
+
+impl Serialize for Pairs<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (k, v) in self.0 {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+struct RecordVisitor;
+
+impl<'de> Visitor<'de> for RecordVisitor {
+    type Value = Record;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a logfmt record")
+    }
+
+    #[inline]
+    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Record, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Record, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut record = Record {
+            pairs: Vec::with_capacity(map.size_hint().unwrap_or(16)),
+        };
+
+        while let Some((k, v)) = map.next_entry()? {
+            record.pairs.push((k, v));
+        }
+
+        Ok(record)
+    }
+}
+
+impl<'de> Deserialize<'de> for Record {
+    #[inline]
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(RECORD_TOKEN, RecordVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Record, Value};
+    use crate::{from_str, to_string, Deserializer, Inference};
+    use serde::Deserialize;
+
+    #[test]
+    fn test_record_round_trip() {
+        let s = "ts=1690232215 message=\"Hello World!\" user.name=\"aurora\" nums.0=1 debug t=true f=false err=null none=\"\" has%20spaces=\"x\"";
+        let record: Record = from_str(s).unwrap();
+
+        assert_eq!(
+            record.iter().collect::<Vec<_>>(),
+            vec![
+                ("ts", &Value::Integer(1690232215)),
+                ("message", &Value::String("Hello World!".to_owned())),
+                ("user.name", &Value::String("aurora".to_owned())),
+                ("nums.0", &Value::Integer(1)),
+                ("debug", &Value::Bare),
+                ("t", &Value::Bool(true)),
+                ("f", &Value::Bool(false)),
+                ("err", &Value::Null),
+                ("none", &Value::String("".to_owned())),
+                ("has spaces", &Value::String("x".to_owned())),
+            ]
+        );
+        assert_eq!(to_string(&record).unwrap(), s);
+
+        assert_eq!(
+            from_str::<Value>("\"a b\"").unwrap(),
+            Value::String("a b".to_owned())
+        );
+        assert_eq!(from_str::<Value>("null").unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        let record: Record = [
+            ("q", "7"),
+            ("n", "null"),
+            ("t", "true"),
+            ("f", "1.5"),
+            ("e", ""),
+            ("s", "aurora"),
+        ]
+        .into_iter()
+        .collect();
+
+        let s = to_string(&record).unwrap();
+        assert_eq!(
+            s,
+            "q=\"7\" n=\"null\" t=\"true\" f=\"1.5\" e=\"\" s=\"aurora\""
+        );
+        assert_eq!(from_str::<Record>(&s).unwrap(), record);
+
+        assert_eq!(to_string(&Value::from("7")).unwrap(), "\"7\"");
+        assert_eq!(from_str::<Value>("\"7\"").unwrap(), Value::from("7"));
+    }
+
+    #[test]
+    fn test_inference() {
+        let s = "i=-42 u=18446744073709551615 f=1.5 e=1e20 n=NaN inf=-inf t=true z=02134 q=\"7\" v=1.2.3";
+        let record: Record = from_str(s).unwrap();
+
+        assert_eq!(record.get("i"), Some(&Value::Integer(-42)));
+        assert_eq!(record.get("u"), Some(&Value::Integer(u64::MAX.into())));
+        assert_eq!(record.get("f"), Some(&Value::Float(1.5)));
+        assert_eq!(record.get("e"), Some(&Value::Float(1e20)));
+        assert!(matches!(record.get("n"), Some(Value::Float(f)) if f.is_nan()));
+        assert_eq!(record.get("inf"), Some(&Value::Float(f64::NEG_INFINITY)));
+        assert_eq!(record.get("t"), Some(&Value::Bool(true)));
+        assert_eq!(record.get("z"), Some(&Value::from("02134")));
+        assert_eq!(record.get("q"), Some(&Value::from("7")));
+        assert_eq!(record.get("v"), Some(&Value::from("1.2.3")));
+
+        let mut de = Deserializer::builder()
+            .with_inference(Inference::Strings)
+            .build("zip=02134 n=5 f debug err=null");
+        let record = Record::deserialize(&mut de).unwrap();
+        assert_eq!(
+            record.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("zip".to_owned(), Value::from("02134")),
+                ("n".to_owned(), Value::from("5")),
+                ("f".to_owned(), Value::Bare),
+                ("debug".to_owned(), Value::Bare),
+                ("err".to_owned(), Value::from("null")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_record() {
+        #[derive(serde::Deserialize)]
+        struct Line {
+            ts: u64,
+            ctx: Record,
+        }
+
+        let line: Line = from_str("ts=5 ctx.user.id=3 ctx.user.name=x ctx.ok").unwrap();
+        assert_eq!(line.ts, 5);
+        assert_eq!(
+            line.ctx.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("user.id".to_owned(), Value::Integer(3)),
+                ("user.name".to_owned(), Value::String("x".to_owned())),
+                ("ok".to_owned(), Value::Bare),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_edit() {
+        let mut record: Record = from_str("a=1 b=2 a=3").unwrap();
+        assert_eq!(record.len(), 3);
+
+        assert_eq!(record.insert("b", 5), Some(Value::Integer(2)));
+        record.push("c", Some(1.5));
+        record.push("d", None::<bool>);
+        record.push("e", true);
+        assert_eq!(record.remove("a"), Some(Value::Integer(1)));
+        assert!(!record.contains_key("a"));
+
+        assert_eq!(to_string(&record).unwrap(), "b=5 c=1.5 d=null e=true");
+
+        let record: Record = [("x", Value::Bare), ("y", Value::from("a b"))]
+            .into_iter()
+            .collect();
+        assert_eq!(to_string(&record).unwrap(), "x y=\"a b\"");
+    }
+}